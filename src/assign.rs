@@ -0,0 +1,274 @@
+use crate::deser::{read_file, DeserError, DeserErrorKind, DeserOptions, Parser, Root};
+use crate::fold::binary;
+use crate::kvp::{Expr, Key, KeyValuePair, NextIndex, Value};
+use crate::lex::{Position, Span, Token};
+use crate::path::position;
+use std::path::Path;
+
+/// Assignment operators, the compound ones applying the binary operator in
+/// front of their `=`.
+const ASSIGNMENTS: &[&str] = &[
+    "=", ".=", "+=", "-=", "*=", "/=", "%=", "**=", "??=", "|=", "&=", "^=", "<<=", ">>=",
+];
+
+/// Deserialize the variables a file builds with assignments, like Drupal's
+/// `settings.php`.
+pub fn deser_vars_file<P: AsRef<Path>>(path: P) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_vars_file_with(path, &DeserOptions::default())
+}
+
+/// Deserialize the variables a file builds with assignments, with options.
+pub fn deser_vars_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let path = path.as_ref();
+    let s = read_file(path, options)?;
+    deser_vars_str_with(&s, options).map_err(|err| err.with_path(path))
+}
+
+/// Deserialize the variables a string builds with assignments, like Drupal's
+/// `settings.php`.
+///
+/// Statements like `$databases['default']['default'] = [...];` run in order.
+/// Missing sets along the indexes are created, `[]` appends, and compound
+/// assignments like `.=` apply their operator. All other statements are
+/// skipped. Each variable becomes one element, keyed by its name without the
+/// `$`.
+///
+/// ```
+/// use caked::{deser_vars_str, Value};
+///
+/// let input = "<?php
+/// $settings['hash_salt'] = 'abc';
+/// $settings['trusted_host_patterns'][] = '^example\\.com$';
+/// ";
+/// let value = Value::Set(deser_vars_str(input).unwrap());
+/// assert_eq!(value.get_path("settings.hash_salt"), Some(&Value::Str("abc".into())));
+/// ```
+pub fn deser_vars_str(input: &str) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_vars_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize the variables a string builds with assignments, with options.
+pub fn deser_vars_str_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    Parser::new(input, *options)
+        .variables()
+        .map_err(|err| err.with_source(input))
+}
+
+/// A `$name[...] = value;` statement.
+#[derive(Debug)]
+pub(crate) struct Assignment {
+    /// Name of the variable, without the `$`.
+    pub(crate) name: String,
+
+    /// Indexes below the variable, `None` for an appending `[]`.
+    pub(crate) keys: Vec<Option<Key>>,
+
+    /// Binary operator of a compound assignment, like `.` for `.=`.
+    pub(crate) op: Option<String>,
+
+    pub(crate) value: Value,
+
+    /// Where the statement starts.
+    pub(crate) position: Position,
+
+    /// Where the assigned value was read from.
+    pub(crate) span: Span,
+}
+
+impl Parser<'_> {
+    /// Runs the assignments to variables in a file, in order, skipping all
+    /// other statements.
+    ///
+    /// Returns one element per variable, keyed by its name.
+    pub(crate) fn variables(&mut self) -> Result<Vec<KeyValuePair>, DeserError> {
+        let mut vars = Value::Set(Vec::new());
+        while let Some((span, t)) = self.peek()? {
+            let (position, name) = match t {
+                Token::Variable(x) => (span.start, x.clone()),
+                _ => {
+                    self.skip_statement()?;
+                    continue;
+                }
+            };
+            self.next()?;
+            let assignment = match self.assignment(name, position)? {
+                Some(x) => x,
+                None => continue,
+            };
+            if let Root::Variable(root) = self.options().root {
+                let set = matches!(assignment.value, Value::Set(_));
+                let plain = assignment.op.is_none() && assignment.keys.is_empty();
+                if assignment.name == root && plain && !set {
+                    let err = DeserErrorKind::InvalidRootVariable(root.to_string());
+                    return Err(DeserError::new(assignment.span.start, err));
+                }
+            }
+            let mut keys = vec![Some(Key::Str(assignment.name))];
+            keys.extend(assignment.keys);
+            let value = match &assignment.op {
+                Some(op) => {
+                    let current = lookup(&vars, &keys).cloned().unwrap_or(Value::Null);
+                    compound(op, current, assignment.value, assignment.span)
+                }
+                None => assignment.value,
+            };
+            assign_at(&mut vars, &keys, value, assignment.position)?;
+        }
+        match vars {
+            Value::Set(x) => Ok(x),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Parses the rest of a statement starting with a variable.
+    ///
+    /// Returns `None` if the statement is not a plain assignment, after
+    /// skipping it.
+    pub(crate) fn assignment(
+        &mut self,
+        name: String,
+        position: Position,
+    ) -> Result<Option<Assignment>, DeserError> {
+        use DeserError as E;
+        use DeserErrorKind as K;
+        let mut keys = Vec::new();
+        let op = loop {
+            match self.peek()? {
+                Some((_, Token::OpenSet)) => self.next()?,
+                Some((_, Token::Operator(x))) if ASSIGNMENTS.contains(&x.as_str()) => {
+                    let op = x[..x.len() - 1].to_string();
+                    self.next()?;
+                    break Some(op).filter(|x| !x.is_empty());
+                }
+                Some((_, Token::Terminator)) => {
+                    self.next()?;
+                    return Ok(None);
+                }
+                _ => {
+                    self.skip_statement()?;
+                    return Ok(None);
+                }
+            };
+            if let Some((_, Token::CloseSet)) = self.peek()? {
+                self.next()?;
+                keys.push(None);
+                continue;
+            }
+            let (span, value) = self.value()?;
+            let key = Key::from_value(&value).ok_or_else(|| E::new(span.start, K::InvalidKey))?;
+            match self.expect()? {
+                (_, Token::CloseSet) => keys.push(Some(key)),
+                (span, t) => return Err(E::new(span.start, K::UnexpectedToken(t))),
+            }
+        };
+        let (span, value) = self.value()?;
+        match self.next()? {
+            None | Some((_, Token::Terminator)) => (),
+            Some((span, t)) => return Err(E::new(span.start, K::UnexpectedToken(t))),
+        }
+        Ok(Some(Assignment {
+            name,
+            keys,
+            op,
+            value,
+            position,
+            span,
+        }))
+    }
+}
+
+/// Assigns a value below a set, creating sets in place of missing values and
+/// nulls like PHP does.
+///
+/// Returns how many keys lead to a value other than a set, if one is in the
+/// way.
+pub(crate) fn assign(target: &mut Value, keys: &[Option<Key>], value: Value) -> Result<(), usize> {
+    let mut target = target;
+    for (depth, key) in keys.iter().enumerate() {
+        if let Value::Null | Value::Bool(false) = target {
+            *target = Value::Set(Vec::new());
+        }
+        let items = match target {
+            Value::Set(x) => x,
+            _ => return Err(depth),
+        };
+        let index = match key {
+            Some(key) => position(items, key),
+            None => None,
+        };
+        let index = match index {
+            Some(x) => x,
+            None => {
+                let key = match key {
+                    Some(key) => key.clone(),
+                    None => {
+                        let mut next = NextIndex::default();
+                        items.iter().for_each(|x| next.observe(&x.key));
+                        next.take()
+                    }
+                };
+                items.push(KeyValuePair::new(key, Value::Null));
+                items.len() - 1
+            }
+        };
+        target = &mut items[index].value;
+    }
+    *target = value;
+    Ok(())
+}
+
+/// Assigns a value like [`assign`] does, failing with the path to a value
+/// in the way, at the position of the statement.
+pub(crate) fn assign_at(
+    target: &mut Value,
+    keys: &[Option<Key>],
+    value: Value,
+    position: Position,
+) -> Result<(), DeserError> {
+    let depth = match assign(target, keys, value) {
+        Ok(()) => return Ok(()),
+        Err(x) => x,
+    };
+    let path = keys[..depth]
+        .iter()
+        .map(|key| match key {
+            Some(Key::Int(x)) => x.to_string(),
+            Some(Key::Str(x)) => x.clone(),
+            None => "[]".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+    Err(DeserError::new(position, DeserErrorKind::InvalidPath(path)))
+}
+
+/// Looks up the value below a set, `None` if it does not exist yet.
+fn lookup<'a>(target: &'a Value, keys: &[Option<Key>]) -> Option<&'a Value> {
+    keys.iter()
+        .try_fold(target, |value, key| match (value, key) {
+            (Value::Set(items), Some(key)) => Some(&items[position(items, key)?].value),
+            _ => None,
+        })
+}
+
+/// Applies the operator of a compound assignment, keeping an expression if
+/// the result is not constant.
+fn compound(op: &str, current: Value, value: Value, span: Span) -> Value {
+    if op == "??" {
+        return match current {
+            Value::Null => value,
+            x => x,
+        };
+    }
+    let operand = |x: &Value| match x {
+        Value::Expr(x) | Value::Error(x) => format!("({})", x.text),
+        x => x.to_string(),
+    };
+    let text = format!("{} {} {}", operand(&current), op, operand(&value));
+    binary(op, current, value).unwrap_or_else(|| Value::Expr(Expr::new(text, span)))
+}
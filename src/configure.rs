@@ -0,0 +1,202 @@
+use crate::deser::{read_file, DeserError, DeserOptions, Parser};
+use crate::kvp::{Key, KeyValuePair, Value};
+use crate::lex::{Span, Token};
+use crate::path::position;
+use std::path::Path;
+
+/// Deserialize the `Configure::write()` calls of a CakePHP 2 `core.php` or
+/// `bootstrap.php` file.
+pub fn deser_configure_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<(Vec<KeyValuePair>, Vec<Span>), DeserError> {
+    deser_configure_file_with(path, &DeserOptions::default())
+}
+
+/// Deserialize the `Configure::write()` calls of a file with options.
+pub fn deser_configure_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<(Vec<KeyValuePair>, Vec<Span>), DeserError> {
+    let path = path.as_ref();
+    let s = read_file(path, options)?;
+    deser_configure_str_with(&s, options).map_err(|err| err.with_path(path))
+}
+
+/// Deserialize the `Configure::write()` calls of a CakePHP 2 `core.php` or
+/// `bootstrap.php`.
+///
+/// The calls run in order, writing their dotted keys into one tree.
+/// `Cache::config()` calls are written below `Cache`, and
+/// `CakePlugin::load()` calls below `Plugins`, with the plugin options or an
+/// empty set.
+///
+/// Returns the tree and the spans of all other statements, which are skipped.
+///
+/// ```
+/// use caked::{deser_configure_str, Value};
+///
+/// let input = "<?php
+/// Configure::write('debug', 2);
+/// Configure::write('Session.defaults', 'php');
+/// App::uses('CakeLog', 'Log');
+/// ";
+/// let (items, skipped) = deser_configure_str(input).unwrap();
+/// let value = Value::Set(items);
+/// assert_eq!(value.get_path("Session.defaults"), Some(&Value::Str("php".into())));
+/// assert_eq!(skipped.len(), 1);
+/// ```
+pub fn deser_configure_str(input: &str) -> Result<(Vec<KeyValuePair>, Vec<Span>), DeserError> {
+    deser_configure_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize the `Configure::write()` calls of a string with options.
+pub fn deser_configure_str_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<(Vec<KeyValuePair>, Vec<Span>), DeserError> {
+    let mut parser = Parser::new(input, *options);
+    let mut tree = Value::Set(Vec::new());
+    let mut skipped = Vec::new();
+    while let Some((span, _)) = parser.peek().map_err(|err| err.with_source(input))? {
+        let start = span.start;
+        let call = parser.static_call().map_err(|err| err.with_source(input))?;
+        let applied = match call {
+            Some((class, method, args)) => apply(&mut tree, &class, &method, args),
+            None => false,
+        };
+        if !applied {
+            skipped.push(Span::new(start, parser.end()));
+        }
+    }
+    match tree {
+        Value::Set(x) => Ok((x, skipped)),
+        _ => unreachable!(),
+    }
+}
+
+impl Parser<'_> {
+    /// Parses a `Class::method(...);` statement.
+    ///
+    /// Returns `None` for any other statement, after skipping it.
+    pub(crate) fn static_call(
+        &mut self,
+    ) -> Result<Option<(String, String, Vec<Value>)>, DeserError> {
+        let depth = self.depth();
+        let call = self.call_parts()?;
+        if call.is_none() {
+            self.skip_statement_from(depth)?;
+        }
+        Ok(call)
+    }
+
+    /// Reads the parts of a `Class::method(...);` statement, stopping at the
+    /// first token that does not fit.
+    fn call_parts(&mut self) -> Result<Option<(String, String, Vec<Value>)>, DeserError> {
+        use Token as T;
+        let class = match self.peek()? {
+            Some((_, T::Identifier(x))) => x.rsplit('\\').next().unwrap_or(x).to_string(),
+            _ => return Ok(None),
+        };
+        self.next()?;
+        if !matches!(self.peek()?, Some((_, T::Operator(x))) if x == "::") {
+            return Ok(None);
+        }
+        self.next()?;
+        let method = match self.peek()? {
+            Some((_, T::Identifier(x))) => x.clone(),
+            _ => return Ok(None),
+        };
+        self.next()?;
+        if !matches!(self.peek()?, Some((_, T::OpenParen))) {
+            return Ok(None);
+        }
+        self.next()?;
+        let mut args = Vec::new();
+        loop {
+            if let Some((_, T::CloseParen)) = self.peek()? {
+                self.next()?;
+                break;
+            }
+            args.push(self.value()?.1);
+            match self.next()? {
+                Some((_, T::Separator)) => (),
+                Some((_, T::CloseParen)) => break,
+                _ => return Ok(None),
+            }
+        }
+        match self.peek()? {
+            None => (),
+            Some((_, T::Terminator)) => {
+                self.next()?;
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some((class, method, args)))
+    }
+}
+
+/// Runs a recognized call against the tree, returning `false` for others.
+fn apply(tree: &mut Value, class: &str, method: &str, args: Vec<Value>) -> bool {
+    let call = (class.to_lowercase(), method.to_lowercase());
+    let mut args = args.into_iter();
+    match (call.0.as_str(), call.1.as_str(), args.next(), args.next()) {
+        ("configure", "write", Some(Value::Str(key)), Some(value)) => {
+            tree.set_path(&key, value);
+        }
+        ("configure", "write", Some(Value::Set(items)), None) => {
+            for kvp in items {
+                tree.set_path(&key_path(&kvp.key), kvp.value);
+            }
+        }
+        ("cache", "config", Some(Value::Str(name)), Some(settings)) => {
+            insert(section(tree, "Cache"), Key::from(name), settings);
+        }
+        ("cakeplugin", "load", Some(Value::Str(name)), options) => {
+            let options = options.unwrap_or_else(|| Value::Set(Vec::new()));
+            insert(section(tree, "Plugins"), Key::from(name), options);
+        }
+        ("cakeplugin", "load", Some(Value::Set(items)), None) => {
+            for kvp in items {
+                let (name, options) = match kvp.value {
+                    Value::Str(name) if matches!(kvp.key, Key::Int(_)) => {
+                        (Key::from(name), Value::Set(Vec::new()))
+                    }
+                    options => (kvp.key, options),
+                };
+                insert(section(tree, "Plugins"), name, options);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn key_path(key: &Key) -> String {
+    match key {
+        Key::Int(x) => x.to_string(),
+        Key::Str(x) => x.clone(),
+    }
+}
+
+/// The set below a top-level key, replacing what is in the way.
+fn section<'a>(tree: &'a mut Value, name: &str) -> &'a mut Vec<KeyValuePair> {
+    let value = match tree.get_path(name) {
+        Some(Value::Set(_)) => tree.get_path_mut(name).unwrap(),
+        _ => {
+            tree.set_path(name, Value::Set(Vec::new()));
+            tree.get_path_mut(name).unwrap()
+        }
+    };
+    match value {
+        Value::Set(x) => x,
+        _ => unreachable!(),
+    }
+}
+
+/// Sets the value of a key in place, or adds it.
+fn insert(items: &mut Vec<KeyValuePair>, key: Key, value: Value) {
+    match position(items, &key) {
+        Some(i) => items[i].value = value,
+        None => items.push(KeyValuePair::new(key, value)),
+    }
+}
@@ -0,0 +1,150 @@
+use crate::kvp::Value;
+use std::collections::HashMap;
+use std::path::{Path, MAIN_SEPARATOR};
+
+/// Constants that bare identifiers resolve to during deserialization.
+///
+/// A new table holds PHP's built-in constants, like `PHP_EOL`, `E_ALL` and
+/// `FILTER_VALIDATE_BOOLEAN`. Names are case sensitive, as in PHP.
+///
+/// ```
+/// use caked::{deser_str_with, ConstantTable, DeserOptions, Value};
+///
+/// let constants = ConstantTable::new().with_cake_paths("/srv/app");
+/// let options = DeserOptions {
+///     constants: Some(&constants),
+///     ..Default::default()
+/// };
+/// let items = deser_str_with("<?php return ['path' => CACHE . 'models'];", &options).unwrap();
+/// assert_eq!(items[0].value, Value::Str("/srv/app/tmp/cache/models".into()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstantTable {
+    values: HashMap<String, Value>,
+}
+
+impl Default for ConstantTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConstantTable {
+    /// Creates a table of PHP's built-in constants.
+    pub fn new() -> Self {
+        let mut table = Self::empty();
+        let eol = if cfg!(windows) { "\r\n" } else { "\n" };
+        let path = if cfg!(windows) { ";" } else { ":" };
+        table.insert("PHP_EOL", Value::Str(eol.to_string()));
+        table.insert(
+            "DIRECTORY_SEPARATOR",
+            Value::Str(MAIN_SEPARATOR.to_string()),
+        );
+        table.insert("PATH_SEPARATOR", Value::Str(path.to_string()));
+        table.insert("PHP_INT_MAX", Value::Int(i64::MAX));
+        table.insert("PHP_INT_MIN", Value::Int(i64::MIN));
+        table.insert("PHP_INT_SIZE", Value::Int(8));
+        table.insert("PHP_FLOAT_EPSILON", Value::Float(f64::EPSILON));
+        table.insert("PHP_FLOAT_MAX", Value::Float(f64::MAX));
+        table.insert("PHP_FLOAT_MIN", Value::Float(f64::MIN_POSITIVE));
+        table.insert("PHP_FLOAT_DIG", Value::Int(15));
+        table.insert("NAN", Value::Float(f64::NAN));
+        table.insert("INF", Value::Float(f64::INFINITY));
+        let ints: &[(&str, i64)] = &[
+            ("E_ERROR", 1),
+            ("E_WARNING", 2),
+            ("E_PARSE", 4),
+            ("E_NOTICE", 8),
+            ("E_CORE_ERROR", 16),
+            ("E_CORE_WARNING", 32),
+            ("E_COMPILE_ERROR", 64),
+            ("E_COMPILE_WARNING", 128),
+            ("E_USER_ERROR", 256),
+            ("E_USER_WARNING", 512),
+            ("E_USER_NOTICE", 1024),
+            ("E_STRICT", 2048),
+            ("E_RECOVERABLE_ERROR", 4096),
+            ("E_DEPRECATED", 8192),
+            ("E_USER_DEPRECATED", 16384),
+            ("E_ALL", 32767),
+            ("FILTER_VALIDATE_INT", 257),
+            ("FILTER_VALIDATE_BOOLEAN", 258),
+            ("FILTER_VALIDATE_BOOL", 258),
+            ("FILTER_VALIDATE_FLOAT", 259),
+            ("FILTER_VALIDATE_REGEXP", 272),
+            ("FILTER_VALIDATE_DOMAIN", 277),
+            ("FILTER_VALIDATE_URL", 273),
+            ("FILTER_VALIDATE_EMAIL", 274),
+            ("FILTER_VALIDATE_IP", 275),
+            ("FILTER_DEFAULT", 516),
+            ("FILTER_UNSAFE_RAW", 516),
+            ("FILTER_NULL_ON_FAILURE", 134_217_728),
+            ("JSON_HEX_TAG", 1),
+            ("JSON_HEX_AMP", 2),
+            ("JSON_HEX_APOS", 4),
+            ("JSON_HEX_QUOT", 8),
+            ("JSON_FORCE_OBJECT", 16),
+            ("JSON_NUMERIC_CHECK", 32),
+            ("JSON_UNESCAPED_SLASHES", 64),
+            ("JSON_PRETTY_PRINT", 128),
+            ("JSON_UNESCAPED_UNICODE", 256),
+            ("JSON_PARTIAL_OUTPUT_ON_ERROR", 512),
+            ("JSON_PRESERVE_ZERO_FRACTION", 1024),
+            ("JSON_THROW_ON_ERROR", 4_194_304),
+        ];
+        for (name, value) in ints {
+            table.insert(*name, Value::Int(*value));
+        }
+        table
+    }
+
+    /// Creates a table without any constants.
+    pub fn empty() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+
+    /// Adds the path constants CakePHP's `config/paths.php` derives from the
+    /// project root, like `APP`, `CONFIG`, `TMP`, `LOGS` and `CACHE`.
+    pub fn with_cake_paths<P: AsRef<Path>>(mut self, root: P) -> Self {
+        let ds = MAIN_SEPARATOR.to_string();
+        let root = root.as_ref().to_string_lossy();
+        let root = match root.trim_end_matches(MAIN_SEPARATOR) {
+            "" => &root[..],
+            x => x,
+        };
+        let dir = |parts: &[&str]| format!("{}{}{}{}", root, ds, parts.join(&ds), ds);
+        let core = format!("{}{}vendor{}cakephp{}cakephp", root, ds, ds, ds);
+        let paths = [
+            ("ROOT", root.to_string()),
+            ("APP_DIR", "src".to_string()),
+            ("APP", dir(&["src"])),
+            ("CONFIG", dir(&["config"])),
+            ("WWW_ROOT", dir(&["webroot"])),
+            ("TESTS", dir(&["tests"])),
+            ("RESOURCES", dir(&["resources"])),
+            ("TMP", dir(&["tmp"])),
+            ("LOGS", dir(&["logs"])),
+            ("CACHE", dir(&["tmp", "cache"])),
+            ("CAKE_CORE_INCLUDE_PATH", core.clone()),
+            ("CORE_PATH", format!("{}{}", core, ds)),
+            ("CAKE", format!("{}{}src{}", core, ds, ds)),
+        ];
+        self.insert("DS", Value::Str(ds.clone()));
+        for (name, value) in paths.iter() {
+            self.insert(*name, Value::Str(value.clone()));
+        }
+        self
+    }
+
+    /// Defines a constant, returning its previous value.
+    pub fn insert<S: Into<String>>(&mut self, name: S, value: Value) -> Option<Value> {
+        self.values.insert(name.into(), value)
+    }
+
+    /// Looks up a constant, ignoring a leading `\` of the global namespace.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name.strip_prefix('\\').unwrap_or(name))
+    }
+}
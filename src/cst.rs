@@ -0,0 +1,490 @@
+use crate::deser::{DeserError, DeserErrorKind, DeserOptions, Parser};
+use crate::kvp::{Key, KeyValuePair, NextIndex, Value};
+use crate::lex::{Position, Span, Token};
+use crate::path::segments;
+use crate::ser::{ser_value, ArrayStyle, SerOptions};
+use std::collections::HashMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::ops::Range;
+
+/// A value in the syntax tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// Source range of the value.
+    pub span: Span,
+
+    /// The parsed value.
+    pub value: Value,
+
+    /// Structure of the value, if it is a set.
+    pub set: Option<SetNode>,
+}
+
+impl Node {
+    /// Looks up a descendant by a dot separated path.
+    ///
+    /// Path segments are keys, or indices of elements without a key. When a
+    /// key occurs more than once, the last element wins, like in PHP.
+    pub fn get(&self, path: &str) -> Option<&Self> {
+        let mut node = self;
+        for segment in segments(path) {
+            let set = node.set.as_ref()?;
+            node = &set.items[set.position(&Key::from(segment))?].value;
+        }
+        Some(node)
+    }
+}
+
+/// A set in the syntax tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetNode {
+    /// Opening delimiter. (`[` or `array(`)
+    pub open: Span,
+
+    /// Closing delimiter.
+    pub close: Span,
+
+    /// Syntax of the set.
+    pub style: ArrayStyle,
+
+    /// Elements of the set.
+    pub items: Vec<Item>,
+}
+
+impl SetNode {
+    /// Index of the element with a key. When a key occurs more than once,
+    /// the last element wins, like in PHP.
+    pub fn position(&self, key: &Key) -> Option<usize> {
+        self.items.iter().rposition(|x| x.key == *key)
+    }
+}
+
+/// An element of a set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// The key, or the index given to an element without one.
+    pub key: Key,
+
+    /// Source range of the key, if the element has one.
+    pub key_span: Option<Span>,
+
+    /// The value.
+    pub value: Node,
+
+    /// The comma after the element, if any.
+    pub comma: Option<Span>,
+}
+
+impl Item {
+    /// Source range of the element, without its comma.
+    pub fn span(&self) -> Span {
+        match self.key_span {
+            Some(span) => Span::new(span.start, self.value.span.end),
+            None => self.value.span,
+        }
+    }
+}
+
+/// A configuration file that keeps its exact source text.
+///
+/// Comments, whitespace, quote styles and trailing commas survive edits, and
+/// only the text of the edited elements changes. New elements copy the
+/// indentation of their siblings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    source: String,
+    root: Option<Node>,
+}
+
+impl Document {
+    /// Parses a file.
+    pub fn parse(input: &str) -> Result<Self, DeserError> {
+        Ok(Self {
+            root: root(input, DeserOptions::default()).map_err(|err| err.with_source(input))?,
+            source: input.to_string(),
+        })
+    }
+
+    /// Current source text.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The returned set, if the file has one.
+    pub fn root(&self) -> Option<&Node> {
+        self.root.as_ref()
+    }
+
+    /// Contents of the returned set, as [`deser_str`](crate::deser_str) reads
+    /// them.
+    pub fn values(&self) -> Vec<KeyValuePair> {
+        match self.root.as_ref().map(|x| &x.value) {
+            Some(Value::Set(x)) => x.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Looks up a value by a dot separated path.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        self.root.as_ref()?.get(path).map(|x| &x.value)
+    }
+
+    /// Sets a value by a dot separated path.
+    ///
+    /// An existing value is replaced. Otherwise the missing keys are appended
+    /// to the deepest set on the path.
+    pub fn set(&mut self, path: &str, value: &Value) -> Result<(), DeserError> {
+        let segments = segments(path);
+        let mut node = self.require_root()?;
+        let mut style = ArrayStyle::default();
+        for (i, segment) in segments.iter().enumerate() {
+            let set = self.as_set(node, path)?;
+            style = set.style;
+            let key = Key::from(*segment);
+            match set.position(&key) {
+                Some(j) => node = &set.items[j].value,
+                None => {
+                    let value = segments[i + 1..].iter().rev().fold(value.clone(), |v, k| {
+                        Value::Set(vec![KeyValuePair::new(Key::from(*k), v)])
+                    });
+                    let mut next = NextIndex::default();
+                    set.items.iter().for_each(|x| next.observe(&x.key));
+                    let key = if next.implicit(&key) {
+                        None
+                    } else {
+                        Some(&key)
+                    };
+                    let edits = self.insertion(set, set.items.len().checked_sub(1), key, &value);
+                    return self.apply(edits);
+                }
+            }
+        }
+        if let Some(set) = &node.set {
+            style = set.style;
+        }
+        let indent = line_indent(&self.source, node.span.start.index);
+        let options = SerOptions {
+            array_style: style,
+            ..Default::default()
+        };
+        let text = ser_value(value, indent, &self.unit(), &options);
+        let edits = vec![(node.span.start.index..node.span.end.index, text)];
+        self.apply(edits)
+    }
+
+    /// Removes an element by a dot separated path, returning its value.
+    ///
+    /// The lines of an element that stands alone are removed entirely, along
+    /// with its trailing comment.
+    pub fn remove(&mut self, path: &str) -> Result<Option<Value>, DeserError> {
+        let (set, i) = match self.find(path) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let src = &self.source;
+        let item = &set.items[i];
+        let removed = item.value.value.clone();
+        let start = item.span().start.index;
+        let after = item.comma.unwrap_or_else(|| item.span()).end.index;
+        let line_start = src[..start].rfind('\n').map_or(0, |x| x + 1);
+        let range = match trivia_end(src, after) {
+            Some(end) if src[line_start..start].trim().is_empty() => {
+                line_start..src[end..].find('\n').map_or(src.len(), |x| end + x + 1)
+            }
+            _ => match (set.items.get(i + 1), i.checked_sub(1)) {
+                (Some(next), _) => start..next.span().start.index,
+                (None, Some(prev)) => set.items[prev].span().end.index..after,
+                (None, None) => set.open.end.index..set.close.start.index,
+            },
+        };
+        self.apply(vec![(range, String::new())])?;
+        Ok(Some(removed))
+    }
+
+    /// Inserts a new element after the one at a dot separated path.
+    pub fn insert_after(
+        &mut self,
+        path: &str,
+        key: Option<&Key>,
+        value: &Value,
+    ) -> Result<(), DeserError> {
+        let (set, i) = match self.find(path) {
+            Some(x) => x,
+            None => {
+                return Err(DeserError::new(
+                    Position::default(),
+                    DeserErrorKind::InvalidPath(path.to_string()),
+                ))
+            }
+        };
+        let edits = self.insertion(set, Some(i), key, value);
+        self.apply(edits)
+    }
+
+    fn require_root(&self) -> Result<&Node, DeserError> {
+        self.root
+            .as_ref()
+            .ok_or_else(|| DeserError::new(Position::default(), DeserErrorKind::InvalidRoot))
+    }
+
+    fn as_set<'a>(&self, node: &'a Node, path: &str) -> Result<&'a SetNode, DeserError> {
+        node.set.as_ref().ok_or_else(|| {
+            DeserError::new(
+                node.span.start,
+                DeserErrorKind::InvalidPath(path.to_string()),
+            )
+        })
+    }
+
+    /// Finds the set holding the element at a path, and its index.
+    fn find(&self, path: &str) -> Option<(&SetNode, usize)> {
+        let segments = segments(path);
+        let (last, parents) = segments.split_last()?;
+        let mut node = self.root.as_ref()?;
+        for segment in parents {
+            let set = node.set.as_ref()?;
+            node = &set.items[set.position(&Key::from(*segment))?].value;
+        }
+        let set = node.set.as_ref()?;
+        Some((set, set.position(&Key::from(*last))?))
+    }
+
+    /// Edits inserting an element after another one, or into an empty set.
+    fn insertion(
+        &self,
+        set: &SetNode,
+        after: Option<usize>,
+        key: Option<&Key>,
+        value: &Value,
+    ) -> Vec<(Range<usize>, String)> {
+        let src = &self.source;
+        let unit = self.unit();
+        let options = SerOptions {
+            array_style: set.style,
+            ..Default::default()
+        };
+        let element = |indent: &str| {
+            let prefix = key.map_or(String::new(), |x| format!("{} => ", x));
+            format!("{}{}", prefix, ser_value(value, indent, &unit, &options))
+        };
+        let prev = match after {
+            Some(i) => &set.items[i],
+            None => {
+                let outer = line_indent(src, set.open.start.index);
+                let indent = format!("{}{}", outer, unit);
+                let text = format!("\n{}{},\n{}", indent, element(&indent), outer);
+                return vec![(set.open.end.index..set.close.start.index, text)];
+            }
+        };
+        let indent = line_indent(src, prev.span().start.index);
+        let text = element(indent);
+        let end = prev.comma.unwrap_or_else(|| prev.span()).end.index;
+        let multiline = src[set.open.end.index..set.close.start.index].contains('\n');
+        match (prev.comma, trivia_end(src, end)) {
+            (Some(_), Some(eol)) if multiline => vec![(eol..eol, format!("\n{}{},", indent, text))],
+            (None, Some(eol)) if multiline => vec![
+                (end..end, ",".to_string()),
+                (eol..eol, format!("\n{}{}", indent, text)),
+            ],
+            (Some(_), _) => vec![(end..end, format!(" {},", text))],
+            (None, _) => vec![(end..end, format!(", {}", text))],
+        }
+    }
+
+    /// Indentation added per nesting level, taken from the first indented
+    /// element.
+    fn unit(&self) -> String {
+        fn find(src: &str, node: &Node) -> Option<String> {
+            let set = node.set.as_ref()?;
+            let outer = line_indent(src, set.open.start.index);
+            for item in &set.items {
+                let inner = line_indent(src, item.span().start.index);
+                if inner.len() > outer.len() && inner.starts_with(outer) {
+                    return Some(inner[outer.len()..].to_string());
+                }
+                if let Some(x) = find(src, &item.value) {
+                    return Some(x);
+                }
+            }
+            None
+        }
+        self.root
+            .as_ref()
+            .and_then(|x| find(&self.source, x))
+            .unwrap_or_else(|| "\t".to_string())
+    }
+
+    /// Applies edits listed in source order, then parses the result.
+    fn apply(&mut self, edits: Vec<(Range<usize>, String)>) -> Result<(), DeserError> {
+        let crlf = self.source.contains("\r\n");
+        let mut source = self.source.clone();
+        for (range, text) in edits.into_iter().rev() {
+            if crlf {
+                source.replace_range(range, &text.replace('\n', "\r\n"));
+            } else {
+                source.replace_range(range, &text);
+            }
+        }
+        self.root = root(&source, DeserOptions::default())?;
+        self.source = source;
+        Ok(())
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Leading whitespace of the line containing `index`.
+pub(crate) fn line_indent(src: &str, index: usize) -> &str {
+    let start = src[..index].rfind('\n').map_or(0, |x| x + 1);
+    let line = &src[start..index];
+    &line[..line.len() - line.trim_start_matches(&[' ', '\t'][..]).len()]
+}
+
+/// Finds the end of the line starting at `index`, if only whitespace and a
+/// comment remain on it.
+fn trivia_end(src: &str, index: usize) -> Option<usize> {
+    let mut end = src[index..].find('\n').map_or(src.len(), |x| index + x);
+    if src[..end].ends_with('\r') {
+        end -= 1;
+    }
+    let rest = src[index..end].trim();
+    if rest.is_empty() || rest.starts_with("//") || rest.starts_with('#') {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// Parses the returned set of a file.
+pub(crate) fn root<'a>(
+    input: &'a str,
+    options: DeserOptions<'a>,
+) -> Result<Option<Node>, DeserError> {
+    let mut parser = Parser::new(input, options);
+    if !parser.preamble()? {
+        return Ok(None);
+    }
+    let node = node(&mut parser)?;
+    if node.set.is_none() {
+        return Err(DeserError::new(
+            node.span.start,
+            DeserErrorKind::InvalidRoot,
+        ));
+    }
+    Ok(Some(node))
+}
+
+/// Parses a value, keeping the structure of sets.
+fn node(parser: &mut Parser<'_>) -> Result<Node, DeserError> {
+    use Token as T;
+    let depth = parser.depth();
+    let (open, style) = match parser.peek()? {
+        Some((span, T::OpenSet)) => (*span, ArrayStyle::Short),
+        Some((span, T::Identifier(x))) if x.eq_ignore_ascii_case("array") => {
+            (*span, ArrayStyle::Long)
+        }
+        _ => {
+            let (span, value) = parser.value()?;
+            return Ok(Node {
+                span,
+                value,
+                set: None,
+            });
+        }
+    };
+    parser.next()?;
+    let (open, close) = match style {
+        ArrayStyle::Short => (open, T::CloseSet),
+        ArrayStyle::Long => match parser.peek()? {
+            Some((span, T::OpenParen)) => {
+                let open = Span::new(open.start, span.end);
+                parser.next()?;
+                (open, T::CloseParen)
+            }
+            _ => return leaf(parser.expr(open.start, depth, 0)?),
+        },
+    };
+    let mut items = Vec::new();
+    let mut next = NextIndex::default();
+    let close = loop {
+        match parser.peek()? {
+            Some((span, t)) if *t == close => {
+                let span = *span;
+                parser.next()?;
+                break span;
+            }
+            _ => (),
+        }
+        let mut value = node(parser)?;
+        let (key, key_span) = match parser.key(&value.value)? {
+            Some(key) => {
+                next.observe(&key);
+                let span = value.span;
+                value = node(parser)?;
+                (key, Some(span))
+            }
+            None => (next.take(), None),
+        };
+        let (span, closed) = parser.element_end(&close)?;
+        items.push(Item {
+            key,
+            key_span,
+            value,
+            comma: if closed { None } else { Some(span) },
+        });
+        if closed {
+            break span;
+        }
+    };
+    if parser.continues()? {
+        return leaf(parser.expr(open.start, depth, 0)?);
+    }
+    Ok(Node {
+        span: Span::new(open.start, close.end),
+        value: Value::Set(pairs(&items, parser.options().strict_keys)?),
+        set: Some(SetNode {
+            open,
+            close,
+            style,
+            items,
+        }),
+    })
+}
+
+/// Values of set elements, letting repeated keys overwrite in place unless
+/// they are reported.
+fn pairs(items: &[Item], strict: bool) -> Result<Vec<KeyValuePair>, DeserError> {
+    let mut pairs: Vec<KeyValuePair> = Vec::new();
+    let mut seen: HashMap<&Key, (usize, Position)> = HashMap::new();
+    for item in items {
+        match seen.get(&item.key) {
+            Some(&(_, first)) if strict => {
+                return Err(DeserError::new(
+                    item.span().start,
+                    DeserErrorKind::DuplicateKey(item.key.clone(), first),
+                ))
+            }
+            Some(&(i, _)) => pairs[i].value = item.value.value.clone(),
+            None => {
+                seen.insert(&item.key, (pairs.len(), item.span().start));
+                pairs.push(KeyValuePair::new(
+                    item.key.clone(),
+                    item.value.value.clone(),
+                ));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn leaf((span, value): (Span, Value)) -> Result<Node, DeserError> {
+    Ok(Node {
+        span,
+        value,
+        set: None,
+    })
+}
@@ -0,0 +1,201 @@
+use crate::deser::{read_file, DeserError, DeserErrorKind, DeserOptions, Parser};
+use crate::kvp::{Key, KeyValuePair, Value};
+use crate::lex::Token;
+use crate::path::position;
+use std::path::Path;
+
+/// Deserialize a CakePHP 2 `app/Config/database.php` file.
+pub fn deser_database_file<P: AsRef<Path>>(path: P) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_database_file_with(path, &DeserOptions::default())
+}
+
+/// Deserialize a CakePHP 2 `app/Config/database.php` file with options.
+pub fn deser_database_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let path = path.as_ref();
+    let s = read_file(path, options)?;
+    deser_database_str_with(&s, options).map_err(|err| err.with_path(path))
+}
+
+/// Deserialize the `DATABASE_CONFIG` class of a CakePHP 2 `database.php`.
+///
+/// Each public property becomes a connection, keyed by the property name,
+/// with its keys renamed to the ones a CakePHP 3 `Datasources` section uses.
+/// A `'datasource' => 'Database/Mysql'` turns into a `className` and a
+/// `driver`, and `login` into `username`. Everything else is kept as is.
+///
+/// Returns no connections if the file has no such class.
+///
+/// ```
+/// use caked::{deser_database_str, Value};
+///
+/// let input = "<?php
+/// class DATABASE_CONFIG {
+///     public $default = array(
+///         'datasource' => 'Database/Mysql',
+///         'login' => 'app',
+///     );
+/// }
+/// ";
+/// let value = Value::Set(deser_database_str(input).unwrap());
+/// let driver = Value::Str("Cake\\Database\\Driver\\Mysql".into());
+/// assert_eq!(value.get_path("default.driver"), Some(&driver));
+/// assert_eq!(value.get_path("default.username"), Some(&Value::Str("app".into())));
+/// ```
+pub fn deser_database_str(input: &str) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_database_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize the `DATABASE_CONFIG` class of a CakePHP 2 `database.php`
+/// with options.
+pub fn deser_database_str_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let mut parser = Parser::new(input, *options);
+    let properties = parser
+        .class("DATABASE_CONFIG")
+        .map_err(|err| err.with_source(input))?;
+    Ok(properties
+        .into_iter()
+        .map(|kvp| KeyValuePair::new(kvp.key, datasource(kvp.value)))
+        .collect())
+}
+
+impl Parser<'_> {
+    /// Reads the public properties of a class, skipping everything else in
+    /// the file.
+    pub(crate) fn class(&mut self, name: &str) -> Result<Vec<KeyValuePair>, DeserError> {
+        use Token as T;
+        loop {
+            match self.peek()? {
+                None => return Ok(Vec::new()),
+                Some((_, T::Identifier(x))) if x.eq_ignore_ascii_case("class") => {
+                    self.next()?;
+                }
+                Some((_, T::Identifier(x)))
+                    if x.eq_ignore_ascii_case("abstract") || x.eq_ignore_ascii_case("final") =>
+                {
+                    self.next()?;
+                    continue;
+                }
+                _ => {
+                    self.skip_statement()?;
+                    continue;
+                }
+            }
+            match self.peek()? {
+                Some((_, T::Identifier(x))) if x.eq_ignore_ascii_case(name) => break,
+                _ => self.skip_statement()?,
+            }
+        }
+        while !matches!(self.next()?, None | Some((_, T::OpenBrace))) {}
+        let mut properties = Vec::new();
+        while let Some((_, t)) = self.peek()? {
+            if *t == T::CloseBrace {
+                self.next()?;
+                break;
+            }
+            if !self.property_modifiers()? {
+                self.skip_statement()?;
+                continue;
+            }
+            loop {
+                let name = match self.expect()? {
+                    (_, T::Variable(x)) => x,
+                    (span, t) => return Err(DeserError::new(span.start, unexpected(t))),
+                };
+                let value = match self.peek()? {
+                    Some((_, T::Operator(x))) if x == "=" => {
+                        self.next()?;
+                        self.value()?.1
+                    }
+                    _ => Value::Null,
+                };
+                let key = Key::Str(name);
+                match position(&properties, &key) {
+                    Some(i) => properties[i] = KeyValuePair::new(key, value),
+                    None => properties.push(KeyValuePair::new(key, value)),
+                }
+                match self.expect()? {
+                    (_, T::Separator) => (),
+                    (_, T::Terminator) => break,
+                    (span, t) => return Err(DeserError::new(span.start, unexpected(t))),
+                }
+            }
+        }
+        Ok(properties)
+    }
+
+    /// Consumes the modifiers and type of a class member.
+    ///
+    /// Returns `true` if the member is a public, non-static property.
+    fn property_modifiers(&mut self) -> Result<bool, DeserError> {
+        use Token as T;
+        let mut public = true;
+        loop {
+            match self.peek()? {
+                Some((_, T::Identifier(x))) => {
+                    let x = x.to_lowercase();
+                    if let "private" | "protected" | "static" | "function" | "const" | "use" =
+                        x.as_str()
+                    {
+                        public = false;
+                    }
+                    self.next()?;
+                }
+                Some((_, T::Operator(x))) if x == "?" => {
+                    self.next()?;
+                }
+                Some((_, T::Variable(_))) => return Ok(public),
+                _ => return Ok(false),
+            }
+        }
+    }
+}
+
+fn unexpected(t: Token) -> DeserErrorKind {
+    match t {
+        Token::Identifier(x) => DeserErrorKind::UnexpectedIdentifier(x),
+        t => DeserErrorKind::UnexpectedToken(t),
+    }
+}
+
+/// Renames the keys of a CakePHP 2 connection to their CakePHP 3 names.
+fn datasource(value: Value) -> Value {
+    let items = match value {
+        Value::Set(x) => x,
+        x => return x,
+    };
+    let mut out = Vec::with_capacity(items.len() + 1);
+    for kvp in items {
+        match (&kvp.key, &kvp.value) {
+            (Key::Str(k), Value::Str(v)) if k == "datasource" => match driver(v) {
+                Some(driver) => {
+                    let class = "Cake\\Database\\Connection".to_string();
+                    out.push(KeyValuePair::new(Key::from("className"), Value::Str(class)));
+                    out.push(KeyValuePair::new(Key::from("driver"), Value::Str(driver)));
+                }
+                None => out.push(kvp),
+            },
+            (Key::Str(k), _) if k == "login" => {
+                out.push(KeyValuePair::new(Key::from("username"), kvp.value))
+            }
+            _ => out.push(kvp),
+        }
+    }
+    Value::Set(out)
+}
+
+/// The CakePHP 3 driver class of a CakePHP 2 core datasource.
+fn driver(datasource: &str) -> Option<String> {
+    let name = datasource.strip_prefix("Database/")?;
+    match name {
+        "Mysql" | "Postgres" | "Sqlite" | "Sqlserver" => {
+            Some(format!("Cake\\Database\\Driver\\{}", name))
+        }
+        _ => None,
+    }
+}
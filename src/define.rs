@@ -0,0 +1,214 @@
+use crate::assign::assign_at;
+use crate::cst::line_indent;
+use crate::deser::{read_file, DeserError, DeserOptions, Parser};
+use crate::kvp::{Key, KeyValuePair, Value};
+use crate::lex::{Position, Span, Token};
+use crate::path::position;
+use crate::ser::{ser_value, SerOptions};
+use std::path::Path;
+
+/// Deserialize the `define()` calls and variables of a file like WordPress's
+/// `wp-config.php`.
+pub fn deser_defines_file<P: AsRef<Path>>(path: P) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_defines_file_with(path, &DeserOptions::default())
+}
+
+/// Deserialize the `define()` calls and variables of a file with options.
+pub fn deser_defines_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let path = path.as_ref();
+    let s = read_file(path, options)?;
+    deser_defines_str_with(&s, options).map_err(|err| err.with_path(path))
+}
+
+/// Deserialize the `define()` calls and variables of a string like
+/// WordPress's `wp-config.php`.
+///
+/// Constants are keyed by their name and variables by their name with the
+/// `$`, in one flat set. Like in PHP, the first `define()` of a constant
+/// wins, and the last assignment to a variable.
+///
+/// ```
+/// use caked::{deser_defines_str, Value};
+///
+/// let input = "<?php
+/// define( 'DB_NAME', 'wordpress' );
+/// $table_prefix = 'wp_';
+/// ";
+/// let value = Value::Set(deser_defines_str(input).unwrap());
+/// assert_eq!(value.get_path("DB_NAME"), Some(&Value::Str("wordpress".into())));
+/// assert_eq!(value.get_path("$table_prefix"), Some(&Value::Str("wp_".into())));
+/// ```
+pub fn deser_defines_str(input: &str) -> Result<Vec<KeyValuePair>, DeserError> {
+    deser_defines_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize the `define()` calls and variables of a string with options.
+pub fn deser_defines_str_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let (items, _) = Parser::new(input, *options)
+        .defines()
+        .map_err(|err| err.with_source(input))?;
+    Ok(items)
+}
+
+/// Sets the value of a constant in the `define()` calls of a string, keeping
+/// the rest of the text as is.
+///
+/// A missing constant is defined after the last `define()`, or at the end of
+/// the file if there is none.
+///
+/// ```
+/// use caked::{update_define, Value};
+///
+/// let input = "<?php\ndefine( 'DB_PASSWORD', 'old' ); // rotated daily\n";
+/// let output = update_define(input, "DB_PASSWORD", &Value::Str("new".into())).unwrap();
+/// assert_eq!(output, "<?php\ndefine( 'DB_PASSWORD', 'new' ); // rotated daily\n");
+/// ```
+pub fn update_define(input: &str, name: &str, value: &Value) -> Result<String, DeserError> {
+    let (_, defines) = Parser::new(input, DeserOptions::default())
+        .defines()
+        .map_err(|err| err.with_source(input))?;
+    let newline = if input.contains("\r\n") { "\r\n" } else { "\n" };
+    let options = SerOptions::default();
+    let mut output = input.to_string();
+    match defines.iter().find(|x| x.name == name) {
+        Some(define) => {
+            let indent = line_indent(input, define.start.index);
+            let text = ser_value(value, indent, "\t", &options).replace('\n', newline);
+            output.replace_range(define.span.start.index..define.span.end.index, &text);
+        }
+        None => {
+            let (index, indent, spaced) = match defines.last() {
+                Some(last) => {
+                    let spaced = input[last.start.index..].starts_with("define( ");
+                    (last.end.index, line_indent(input, last.start.index), spaced)
+                }
+                None => (input.len(), "", false),
+            };
+            let text = ser_value(value, indent, "\t", &options);
+            let text = match spaced {
+                true => format!("define( {}, {} );", Key::from(name), text),
+                false => format!("define({}, {});", Key::from(name), text),
+            };
+            let text = match (
+                defines.is_empty(),
+                input.ends_with('\n') || input.is_empty(),
+            ) {
+                (false, _) => format!("\n{}{}", indent, text),
+                (true, true) => format!("{}\n", text),
+                (true, false) => format!("\n{}\n", text),
+            };
+            output.insert_str(index, &text.replace('\n', newline));
+        }
+    }
+    Parser::new(&output, DeserOptions::default()).defines()?;
+    Ok(output)
+}
+
+/// A `define()` call.
+#[derive(Debug)]
+struct Define {
+    name: String,
+
+    /// Where the call starts.
+    start: Position,
+
+    /// Source range of the value.
+    span: Span,
+
+    /// End of the statement.
+    end: Position,
+}
+
+impl Parser<'_> {
+    /// Reads the `define()` calls anywhere in a file, and the assignments
+    /// that start statements.
+    fn defines(&mut self) -> Result<(Vec<KeyValuePair>, Vec<Define>), DeserError> {
+        use Token as T;
+        let mut tree = Value::Set(Vec::new());
+        let mut defines = Vec::new();
+        let mut statement = true;
+        while let Some((span, t)) = self.peek()? {
+            let start = span.start;
+            match t {
+                T::Identifier(x) if x.trim_start_matches('\\').eq_ignore_ascii_case("define") => {
+                    self.next()?;
+                    let define = self.define(start)?;
+                    statement = define.is_some();
+                    if let Some((define, value)) = define {
+                        let items = match &mut tree {
+                            Value::Set(x) => x,
+                            _ => unreachable!(),
+                        };
+                        let key = Key::from(define.name.as_str());
+                        if position(items, &key).is_none() {
+                            items.push(KeyValuePair::new(key, value));
+                            defines.push(define);
+                        }
+                    }
+                    continue;
+                }
+                T::Variable(x) if statement => {
+                    let name = x.clone();
+                    self.next()?;
+                    if let Some(assignment) = self.assignment(name, start)? {
+                        let mut keys = vec![Some(Key::Str(format!("${}", assignment.name)))];
+                        keys.extend(assignment.keys);
+                        assign_at(&mut tree, &keys, assignment.value, assignment.position)?;
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+            let (_, t) = self.expect()?;
+            statement = matches!(t, T::Terminator | T::OpenBrace | T::CloseBrace);
+        }
+        match tree {
+            Value::Set(x) => Ok((x, defines)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Parses the arguments of a `define()` call.
+    ///
+    /// Returns `None` if the name is not a string or the call is malformed.
+    fn define(&mut self, start: Position) -> Result<Option<(Define, Value)>, DeserError> {
+        use Token as T;
+        if !matches!(self.peek()?, Some((_, T::OpenParen))) {
+            return Ok(None);
+        }
+        self.next()?;
+        let name = match self.value()? {
+            (_, Value::Str(x)) => x,
+            _ => return Ok(None),
+        };
+        if !matches!(self.next()?, Some((_, T::Separator))) {
+            return Ok(None);
+        }
+        let (span, value) = self.value()?;
+        if let Some((_, T::Separator)) = self.peek()? {
+            self.next()?;
+            if !matches!(self.peek()?, Some((_, T::CloseParen))) {
+                self.value()?;
+            }
+        }
+        if !matches!(self.next()?, Some((_, T::CloseParen))) {
+            return Ok(None);
+        }
+        if let Some((_, T::Terminator)) = self.peek()? {
+            self.next()?;
+        }
+        let define = Define {
+            name,
+            start,
+            span,
+            end: self.end(),
+        };
+        Ok(Some((define, value)))
+    }
+}
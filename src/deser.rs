@@ -1,11 +1,14 @@
-use crate::kvp::{KeyValuePair, Value};
-use crate::lex::{eof, lex, LexError, LexErrorKind, Position, State, Token};
-use std::collections::VecDeque;
-use std::fs::File;
-use std::io::Read;
-use std::mem::swap;
-use std::path::Path;
+use crate::constant::ConstantTable;
+use crate::env::{Env, EnvMode, Filter};
+use crate::fold::fold;
+use crate::kvp::{Expr, Key, KeyValuePair, NextIndex, Value};
+use crate::lex::{LexError, LexErrorKind, Lexer, Position, Span, Token, Tokens};
+use std::collections::HashMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::num::IntErrorKind;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 
 /// Deserialization error kind.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -27,6 +30,67 @@ pub enum DeserErrorKind {
 
     /// Integer casting error.
     IntCast(String),
+
+    /// Operator where none is allowed.
+    UnexpectedOperator(String),
+
+    /// Unexpected token.
+    UnexpectedToken(Token),
+
+    /// File does not return a set.
+    InvalidRoot,
+
+    /// File does not assign a set to its root variable, named without the
+    /// `$`.
+    InvalidRootVariable(String),
+
+    /// Invalid arguments to a function call.
+    InvalidArguments(String),
+
+    /// Path that does not lead through sets.
+    InvalidPath(String),
+
+    /// Key repeated in a set, with the position of its first occurrence.
+    DuplicateKey(Key, Position),
+
+    /// Error reported while mapping values onto Rust types.
+    Custom(String),
+
+    /// File could not be read.
+    Io(std::io::ErrorKind, String),
+
+    /// File is not valid UTF-8, at the first invalid byte.
+    InvalidUtf8,
+}
+
+impl Display for DeserErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use DeserErrorKind as K;
+        use LexErrorKind as L;
+        match self {
+            K::Lex(L::EOF) => write!(f, "unexpected end of file"),
+            K::Lex(L::Unexpected(c)) => write!(f, "unexpected character {:?}", c),
+            K::Lex(L::InvalidUnicode(x)) => write!(f, "invalid codepoint U+{:X}", x),
+            K::Lex(L::InvalidIndentation) => write!(f, "invalid heredoc indentation"),
+            K::UnexpectedIdentifier(x) => write!(f, "unexpected identifier `{}`", x),
+            K::InvalidKey => write!(f, "invalid key"),
+            K::MissingComma => write!(f, "missing comma"),
+            K::FloatCast(x) => write!(f, "invalid float `{}`", x),
+            K::IntCast(x) => write!(f, "invalid integer `{}`", x),
+            K::UnexpectedOperator(x) => write!(f, "unexpected operator `{}`", x),
+            K::UnexpectedToken(x) => write!(f, "unexpected token {:?}", x),
+            K::InvalidRoot => write!(f, "file does not return a set"),
+            K::InvalidRootVariable(x) => write!(f, "file does not assign a set to `${}`", x),
+            K::InvalidArguments(x) => write!(f, "invalid arguments to `{}`", x),
+            K::InvalidPath(x) => write!(f, "path `{}` does not lead through sets", x),
+            K::DuplicateKey(key, first) => {
+                write!(f, "duplicate key {}, first used at {}", key, first)
+            }
+            K::Custom(x) => write!(f, "{}", x),
+            K::Io(_, x) => write!(f, "cannot read file: {}", x),
+            K::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
 }
 
 /// Deserialization error.
@@ -37,12 +101,73 @@ pub struct DeserError {
 
     /// Error kind.
     pub kind: DeserErrorKind,
+
+    path: Option<PathBuf>,
+    source_text: Option<Arc<str>>,
+    io: Option<Arc<std::io::Error>>,
 }
 
 impl DeserError {
     /// Creates a new deserialization error.
     pub fn new(position: Position, kind: DeserErrorKind) -> Self {
-        Self { position, kind }
+        Self {
+            position,
+            kind,
+            path: None,
+            source_text: None,
+            io: None,
+        }
+    }
+
+    /// Attaches the file the error occurred in.
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Attaches the source text the error occurred in.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source_text = Some(source.into());
+        self
+    }
+
+    /// File the error occurred in, if read with [`deser_file`].
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Source text the error occurred in.
+    pub fn source_text(&self) -> Option<&str> {
+        self.source_text.as_deref()
+    }
+}
+
+impl Display for DeserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{} at {}", self.kind, self.position)?;
+        match &self.path {
+            Some(path) => write!(f, " in {}", path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The I/O error a file could not be read with is the source.
+impl std::error::Error for DeserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.io {
+            Some(err) => Some(err.as_ref()),
+            None => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DeserError {
+    fn from(err: std::io::Error) -> Self {
+        let kind = DeserErrorKind::Io(err.kind(), err.to_string());
+        let mut this = Self::new(Position::default(), kind);
+        this.io = Some(Arc::new(err));
+        this
     }
 }
 
@@ -52,170 +177,737 @@ impl From<LexError> for DeserError {
     }
 }
 
+/// Deserialization options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeserOptions<'a> {
+    /// Handling of `env()` calls.
+    pub env: EnvMode<'a>,
+
+    /// Reports repeated keys in a set as errors, instead of letting the last
+    /// value overwrite the first in place like PHP does.
+    pub strict_keys: bool,
+
+    /// Encoding of files read with [`deser_file_with`].
+    pub encoding: Encoding,
+
+    /// Constants that bare identifiers resolve to. Without a table they are
+    /// kept as expressions.
+    pub constants: Option<&'a ConstantTable>,
+
+    /// Where files keep their configuration.
+    pub root: Root<'a>,
+}
+
+/// Where a file keeps its configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Root<'a> {
+    /// A returned set, like `return [...];`.
+    #[default]
+    Return,
+
+    /// A variable built by assignments, like CakePHP 2's `PhpReader` reads
+    /// from `$config = [...];` and `$config['Key'] = value;`. The name is
+    /// given without the `$`.
+    Variable(&'a str),
+}
+
+/// Text encoding of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Encoding {
+    /// UTF-8, with or without a byte order mark.
+    #[default]
+    Utf8,
+
+    /// ISO-8859-1, as used by legacy configuration files.
+    Latin1,
+}
+
+impl Encoding {
+    /// Decodes the contents of a file.
+    pub fn decode(self, bytes: &[u8]) -> Result<String, DeserError> {
+        match self {
+            Self::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(x) => Ok(x.to_string()),
+                Err(err) => {
+                    let valid = &bytes[..err.valid_up_to()];
+                    let mut position = Position::default();
+                    String::from_utf8_lossy(valid)
+                        .chars()
+                        .for_each(|c| position.advance(c));
+                    let err = DeserError::new(position, DeserErrorKind::InvalidUtf8);
+                    Err(err.with_source(&String::from_utf8_lossy(bytes)))
+                }
+            },
+            Self::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+}
+
 /// Deserialize from a file.
 pub fn deser_file<P: AsRef<Path>>(path: P) -> Result<Vec<KeyValuePair>, DeserError> {
-    // we could do this streaming but utf-8 reading is weird
-    let mut f = File::open(path).unwrap();
-    let mut s = String::new();
-    f.read_to_string(&mut s).unwrap();
-    deser_str(&s)
+    deser_file_with(path, &DeserOptions::default())
+}
+
+/// Deserialize from a file with options.
+pub fn deser_file_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let path = path.as_ref();
+    let s = read_file(path, options)?;
+    deser_str_with(&s, options).map_err(|err| err.with_path(path))
+}
+
+/// Reads and decodes a file, attaching the path to errors.
+pub(crate) fn read_file(path: &Path, options: &DeserOptions<'_>) -> Result<String, DeserError> {
+    let bytes = std::fs::read(path).map_err(|err| DeserError::from(err).with_path(path))?;
+    options
+        .encoding
+        .decode(&bytes)
+        .map_err(|err| err.with_path(path))
 }
 
 /// Deserialize from a string.
 pub fn deser_str(input: &str) -> Result<Vec<KeyValuePair>, DeserError> {
-    use DeserError as E;
-    use DeserErrorKind as K;
-    use Token as T;
-    use Value as V;
-    let mut tokens = VecDeque::new();
-    {
-        let mut s = State::default();
-        let mut p = Position::default();
-        let mut tp = Position::default();
-        let mut b = String::new();
-        let mut cp = 0;
-        lex(
-            &mut s,
-            &mut p,
-            &mut tp,
-            &mut b,
-            &mut cp,
-            input.chars(),
-            &mut tokens,
-        )?;
-        eof(s, p, tp, b, &mut tokens)?;
-    };
-    let mut data: Vec<(Option<String>, Vec<KeyValuePair>)> = Vec::new();
-    let mut current: Vec<KeyValuePair> = Vec::new();
-    let mut pair_key: Option<String> = None;
-    let mut pair_value = V::Null;
-    let mut pair_set = false;
-    let mut lexer_break = false;
-    let mut first_open = true;
-    for (p, t) in tokens {
-        match t {
-            T::Float(x) => {
-                if pair_set {
-                    return Err(E::new(p, K::MissingComma));
-                }
-                match f64::from_str(&x) {
-                    Ok(v) => pair_value = V::Float(v),
-                    Err(_) => return Err(E::new(p, K::IntCast(x))),
-                }
-                pair_set = true;
-            }
-            T::Int(x) => {
-                if pair_set {
-                    return Err(E::new(p, K::MissingComma));
-                }
-                match i64::from_str(&x) {
-                    Ok(v) => pair_value = V::Int(v),
-                    Err(_) => return Err(E::new(p, K::IntCast(x))),
-                }
-                pair_set = true;
-            }
-            T::DoubleQuote(x) | T::SingleQuote(x) => {
-                if pair_set {
-                    return Err(E::new(p, K::MissingComma));
-                }
-                pair_value = V::Str(x);
-                pair_set = true;
-            }
-            T::Assignment => {
-                let mut pv = V::Null;
-                swap(&mut pv, &mut pair_value);
-                match pv {
-                    V::Str(v) => pair_key = Some(v),
-                    _ => return Err(E::new(p, K::InvalidKey)),
-                }
-
-                pair_value = V::Null;
-                pair_set = false;
-            }
-            T::Identifier(x) => {
-                let lower = x.to_lowercase();
-                match lower.as_str() {
-                    "true" => {
-                        if pair_set {
-                            return Err(E::new(p, K::MissingComma));
-                        }
-                        pair_value = V::Bool(true);
-                        pair_set = true;
+    deser_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize from a string with options.
+pub fn deser_str_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    Parser::new(input, *options)
+        .document()
+        .map_err(|err| err.with_source(input))
+}
+
+/// Deserialize from a string, recovering from errors.
+///
+/// Returns every error found along with a best-effort result, in which
+/// broken elements are kept as [`Value::Error`] nodes.
+pub fn deser_str_recover(input: &str) -> (Vec<KeyValuePair>, Vec<DeserError>) {
+    deser_str_recover_with(input, &DeserOptions::default())
+}
+
+/// Deserialize from a string with options, recovering from errors.
+pub fn deser_str_recover_with(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> (Vec<KeyValuePair>, Vec<DeserError>) {
+    let (items, errors) = Parser::new(input, *options).document_recover();
+    let errors = errors.into_iter().map(|x| x.with_source(input)).collect();
+    (items, errors)
+}
+
+/// Recursive descent parser over the lexer tokens.
+pub(crate) struct Parser<'a> {
+    source: &'a str,
+    tokens: Tokens<'a>,
+    peeked: Option<(Span, Token)>,
+    end: Position,
+    depth: usize,
+    options: DeserOptions<'a>,
+    errors: Option<Vec<DeserError>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates a new parser.
+    pub(crate) fn new(input: &'a str, options: DeserOptions<'a>) -> Self {
+        Self {
+            source: input,
+            tokens: Lexer::tokens(input),
+            peeked: None,
+            end: Position::default(),
+            depth: 0,
+            options,
+            errors: None,
+        }
+    }
+
+    /// Looks at the next token without consuming it.
+    pub(crate) fn peek(&mut self) -> Result<Option<&(Span, Token)>, DeserError> {
+        if self.peeked.is_none() {
+            self.peeked = self.tokens.next().transpose()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    /// Consumes the next token.
+    pub(crate) fn next(&mut self) -> Result<Option<(Span, Token)>, DeserError> {
+        self.peek()?;
+        let next = self.peeked.take();
+        if let Some((span, t)) = &next {
+            self.end = span.end;
+            match t {
+                Token::OpenSet | Token::OpenParen | Token::OpenBrace => self.depth += 1,
+                Token::CloseSet | Token::CloseParen | Token::CloseBrace => {
+                    self.depth = self.depth.saturating_sub(1)
+                }
+                _ => (),
+            }
+        }
+        Ok(next)
+    }
+
+    /// Consumes the next token, which must exist.
+    pub(crate) fn expect(&mut self) -> Result<(Span, Token), DeserError> {
+        match self.next()? {
+            Some(x) => Ok(x),
+            None => Err(DeserError::new(
+                self.end,
+                DeserErrorKind::Lex(LexErrorKind::EOF),
+            )),
+        }
+    }
+
+    /// Options the parser was created with.
+    pub(crate) fn options(&self) -> &DeserOptions<'a> {
+        &self.options
+    }
+
+    /// End of the last consumed token.
+    pub(crate) fn end(&self) -> Position {
+        self.end
+    }
+
+    /// Nesting depth of the last consumed token.
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Skips the statements in front of the returned value.
+    ///
+    /// Returns `false` if the file has no value.
+    pub(crate) fn preamble(&mut self) -> Result<bool, DeserError> {
+        use Token as T;
+        while let Some((_, T::Identifier(x))) = self.peek()? {
+            match x.to_lowercase().as_str() {
+                "return" => {
+                    self.next()?;
+                    break;
+                }
+                "use" | "namespace" | "declare" => self.skip_statement()?,
+                _ => break,
+            }
+        }
+        Ok(self.peek()?.is_some())
+    }
+
+    /// Parses a whole file, returning the contents of its outermost set.
+    pub(crate) fn document(&mut self) -> Result<Vec<KeyValuePair>, DeserError> {
+        if let Root::Variable(name) = self.options.root {
+            let key = Key::Str(name.to_string());
+            let vars = self.variables()?;
+            return match vars.into_iter().find(|x| x.key == key).map(|x| x.value) {
+                Some(Value::Set(x)) => Ok(x),
+                _ => {
+                    let kind = DeserErrorKind::InvalidRootVariable(name.to_string());
+                    Err(DeserError::new(self.end, kind))
+                }
+            };
+        }
+        if !self.preamble()? {
+            return Ok(Vec::new());
+        }
+        match self.value()? {
+            (_, Value::Set(x)) => Ok(x),
+            (span, _) => Err(DeserError::new(span.start, DeserErrorKind::InvalidRoot)),
+        }
+    }
+
+    /// Parses a whole file, collecting errors instead of stopping at the
+    /// first one.
+    pub(crate) fn document_recover(mut self) -> (Vec<KeyValuePair>, Vec<DeserError>) {
+        self.errors = Some(Vec::new());
+        let items = match self.document() {
+            Ok(x) => x,
+            Err(err) => {
+                self.report(err).ok();
+                Vec::new()
+            }
+        };
+        (items, self.errors.unwrap_or_default())
+    }
+
+    /// Records an error when recovering, or returns it otherwise.
+    ///
+    /// Errors after a lexer error are dropped, since the lexer stops there,
+    /// and so are errors repeated at the same position.
+    fn report(&mut self, err: DeserError) -> Result<(), DeserError> {
+        let errors = match &mut self.errors {
+            Some(x) => x,
+            None => return Err(err),
+        };
+        match errors.last() {
+            Some(last) if matches!(last.kind, DeserErrorKind::Lex(_)) => (),
+            Some(last) if last.position == err.position && last.kind == err.kind => (),
+            _ => errors.push(err),
+        }
+        Ok(())
+    }
+
+    /// Skips to the end of a broken set element, which is the next comma or
+    /// closing token at the depth of the set. Stray closing tokens of other
+    /// kinds are skipped over.
+    ///
+    /// Returns where the element ends, and `true` if the set ended.
+    fn resync(&mut self, depth: usize, close: &Token) -> Result<(Position, bool), DeserError> {
+        use Token as T;
+        self.depth = self.depth.max(depth);
+        loop {
+            let top = self.depth == depth;
+            let end = self.end;
+            match self.peek() {
+                Err(err) => {
+                    self.report(err)?;
+                    return Ok((end, true));
+                }
+                Ok(None) => return Ok((end, true)),
+                Ok(Some((_, T::Terminator))) if top => return Ok((end, true)),
+                Ok(Some((_, t))) if top && t == close => {
+                    self.next()?;
+                    return Ok((end, true));
+                }
+                Ok(Some((_, T::Separator))) if top => {
+                    self.next()?;
+                    return Ok((end, false));
+                }
+                Ok(Some((_, T::CloseSet)))
+                | Ok(Some((_, T::CloseParen)))
+                | Ok(Some((_, T::CloseBrace)))
+                    if top =>
+                {
+                    self.next()?;
+                    self.depth = depth;
+                }
+                Ok(_) => {
+                    self.next()?;
+                }
+            }
+        }
+    }
+
+    /// Skips a statement, up to and including its terminator.
+    pub(crate) fn skip_statement(&mut self) -> Result<(), DeserError> {
+        self.skip_statement_from(self.depth)
+    }
+
+    /// Skips the rest of a statement that started at a nesting depth, up to
+    /// and including its terminator.
+    pub(crate) fn skip_statement_from(&mut self, depth: usize) -> Result<(), DeserError> {
+        while let Some((_, t)) = self.next()? {
+            if self.depth <= depth && (t == Token::Terminator || t == Token::CloseBrace) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a single value, folding constant expressions and keeping those
+    /// it does not understand verbatim.
+    pub(crate) fn value(&mut self) -> Result<(Span, Value), DeserError> {
+        use Token as T;
+        let depth = self.depth;
+        let (start, arrow) = match self.peek()? {
+            Some((span, T::Identifier(x))) => (span.start, x.eq_ignore_ascii_case("fn")),
+            Some((span, _)) => (span.start, false),
+            None => return self.expect().map(|_| unreachable!()),
+        };
+        match self.simple_value()? {
+            Some(x) if !self.continues()? => Ok(x),
+            _ => self.expr(start, depth, arrow as usize),
+        }
+    }
+
+    /// Checks whether the token after a complete value continues it into a
+    /// larger expression.
+    pub(crate) fn continues(&mut self) -> Result<bool, DeserError> {
+        use Token as T;
+        match self.peek()? {
+            None
+            | Some((_, T::Separator))
+            | Some((_, T::Assignment))
+            | Some((_, T::CloseSet))
+            | Some((_, T::CloseParen))
+            | Some((_, T::CloseBrace))
+            | Some((_, T::Terminator)) => Ok(false),
+            Some((_, T::Operator(_))) | Some((_, T::OpenSet)) | Some((_, T::OpenParen)) => Ok(true),
+            Some((_, T::Identifier(x)))
+                if ["and", "or", "xor", "instanceof"]
+                    .iter()
+                    .any(|op| x.eq_ignore_ascii_case(op)) =>
+            {
+                Ok(true)
+            }
+            Some((span, _)) => {
+                let err = DeserError::new(span.start, DeserErrorKind::MissingComma);
+                self.report(err)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Skips the rest of an expression that started at `start`, folding it
+    /// to a value if it is constant and keeping its text otherwise.
+    pub(crate) fn expr(
+        &mut self,
+        start: Position,
+        depth: usize,
+        arrows: usize,
+    ) -> Result<(Span, Value), DeserError> {
+        self.skip_expr(depth, arrows)?;
+        let span = Span::new(start, self.end);
+        let text = &self.source[start.index..span.end.index];
+        match fold(text, self.options.constants) {
+            Some(value) => Ok((span, value)),
+            None => Ok((span, Value::Expr(Expr::new(text.to_string(), span)))),
+        }
+    }
+
+    /// Skips the rest of an expression, stopping before the token that ends
+    /// it at the given depth.
+    ///
+    /// `arrows` counts the arrow functions whose `=>` is still to come.
+    fn skip_expr(&mut self, depth: usize, mut arrows: usize) -> Result<(), DeserError> {
+        use Token as T;
+        loop {
+            let top = self.depth == depth;
+            match self.peek()? {
+                None => return Ok(()),
+                Some((_, t)) if top => match t {
+                    T::Separator | T::CloseSet | T::CloseParen | T::CloseBrace | T::Terminator => {
+                        return Ok(())
                     }
-                    "false" => {
-                        if pair_set {
-                            return Err(E::new(p, K::MissingComma));
-                        }
-                        pair_value = V::Bool(false);
-                        pair_set = true;
+                    T::Assignment if arrows == 0 => return Ok(()),
+                    T::Assignment => arrows -= 1,
+                    T::Identifier(x) if x.eq_ignore_ascii_case("fn") => arrows += 1,
+                    _ => (),
+                },
+                _ => (),
+            }
+            self.next()?;
+        }
+    }
+
+    /// Parses a value made of literals, sets and known calls.
+    ///
+    /// Returns `None` on anything else, leaving the caller to skip it as an
+    /// expression.
+    fn simple_value(&mut self) -> Result<Option<(Span, Value)>, DeserError> {
+        use DeserError as E;
+        use DeserErrorKind as K;
+        use Token as T;
+        use Value as V;
+        let (span, t) = self.expect()?;
+        let p = span.start;
+        let value = match t {
+            T::Int(x) => int_value(&x).map_err(|kind| E::new(p, kind))?,
+            T::Float(x) => float_value(&x).map_err(|kind| E::new(p, kind))?,
+            T::Operator(x) if x == "-" || x == "+" => {
+                let (inner, value) = match self.peek()? {
+                    Some((_, T::Int(_))) | Some((_, T::Float(_))) => match self.simple_value()? {
+                        Some(x) => x,
+                        None => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+                let value = match value {
+                    V::Int(v) if x == "-" => match v.checked_neg() {
+                        Some(v) => V::Int(v),
+                        None => V::Float(-(v as f64)),
+                    },
+                    V::Float(v) if x == "-" => V::Float(-v),
+                    v => v,
+                };
+                return Ok(Some((Span::new(span.start, inner.end), value)));
+            }
+            T::DoubleQuote(x) | T::SingleQuote(x) => V::Str(x),
+            T::Bytes(x) => V::Bytes(x),
+            T::Identifier(x) => match x.to_lowercase().as_str() {
+                "true" => V::Bool(true),
+                "false" => V::Bool(false),
+                "null" => V::Null,
+                "array" => match self.peek()? {
+                    Some((_, T::OpenParen)) => {
+                        self.next()?;
+                        V::Set(self.set(T::CloseParen)?)
                     }
-                    "null" => {
-                        if pair_set {
-                            return Err(E::new(p, K::MissingComma));
-                        }
-                        pair_value = V::Null;
-                        pair_set = true;
+                    _ => return Ok(None),
+                },
+                "env" => match self.env()? {
+                    Some(x) => x,
+                    None => return Ok(None),
+                },
+                "filter_var" => match self.filter_var()? {
+                    Some(x) => x,
+                    None => return Ok(None),
+                },
+                _ => match self.options.constants.and_then(|c| c.get(&x)) {
+                    Some(x) => x.clone(),
+                    None => return Ok(None),
+                },
+            },
+            T::OpenSet => V::Set(self.set(T::CloseSet)?),
+            T::Separator
+            | T::Assignment
+            | T::CloseSet
+            | T::CloseParen
+            | T::CloseBrace
+            | T::Terminator => return Err(E::new(p, K::UnexpectedToken(t))),
+            _ => return Ok(None),
+        };
+        Ok(Some((Span::new(span.start, self.end), value)))
+    }
+
+    /// Consumes the closing parenthesis of a call, after an optional comma.
+    ///
+    /// Returns `false` if the call has more arguments.
+    fn close_call(&mut self) -> Result<bool, DeserError> {
+        if let Some((_, Token::Separator)) = self.peek()? {
+            self.next()?;
+        }
+        if let Some((_, Token::CloseParen)) = self.peek()? {
+            self.next()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Parses the arguments of an `env()` call.
+    fn env(&mut self) -> Result<Option<Value>, DeserError> {
+        use Token as T;
+        if !matches!(self.peek()?, Some((_, T::OpenParen))) {
+            return Ok(None);
+        }
+        self.next()?;
+        let name = match self.value()? {
+            (_, Value::Str(x)) => x,
+            _ => return Ok(None),
+        };
+        let mut default = None;
+        if let Some((_, T::Separator)) = self.peek()? {
+            self.next()?;
+            if !matches!(self.peek()?, Some((_, T::CloseParen))) {
+                default = Some(self.value()?.1);
+            }
+        }
+        if !self.close_call()? {
+            return Ok(None);
+        }
+        let env = Env::new(name, default);
+        Ok(Some(match self.options.env {
+            EnvMode::Keep => Value::Env(env),
+            EnvMode::Evaluate(source) => env.evaluate(source),
+        }))
+    }
+
+    /// Parses the arguments of a `filter_var()` call.
+    fn filter_var(&mut self) -> Result<Option<Value>, DeserError> {
+        use Token as T;
+        if !matches!(self.peek()?, Some((_, T::OpenParen))) {
+            return Ok(None);
+        }
+        self.next()?;
+        let value = self.value()?.1;
+        if !matches!(self.next()?, Some((_, T::Separator))) {
+            return Ok(None);
+        }
+        let filter = match self.peek()? {
+            Some((_, T::Identifier(x))) => Filter::from_name(x),
+            _ => None,
+        };
+        let filter = match filter {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        self.next()?;
+        if !self.close_call()? {
+            return Ok(None);
+        }
+        Ok(Some(match value {
+            Value::Env(mut env) if env.filter.is_none() => {
+                env.filter = Some(filter);
+                Value::Env(env)
+            }
+            Value::Expr(_) => return Ok(None),
+            x => filter.apply(&x),
+        }))
+    }
+
+    /// Parses the elements of a set, up to and including the closing token.
+    ///
+    /// When recovering, broken elements are kept as error nodes.
+    fn set(&mut self, close: Token) -> Result<Vec<KeyValuePair>, DeserError> {
+        let mut current: Vec<KeyValuePair> = Vec::new();
+        let mut next = NextIndex::default();
+        let mut seen: HashMap<Key, (usize, Position)> = HashMap::new();
+        let depth = self.depth;
+        let recovering = self.errors.is_some();
+        loop {
+            let start = match self.peek()? {
+                Some((_, t)) if *t == close => {
+                    self.next()?;
+                    return Ok(current);
+                }
+                Some((span, Token::Separator)) if recovering => {
+                    let err = DeserError::new(
+                        span.start,
+                        DeserErrorKind::UnexpectedToken(Token::Separator),
+                    );
+                    self.next()?;
+                    self.report(err)?;
+                    continue;
+                }
+                Some((span, _)) => span.start,
+                None => self.end,
+            };
+            let mut key = None;
+            let mut value_start = start;
+            let (value, closed) = match self.element(&mut key, &mut value_start) {
+                Ok(value) => match self.element_end(&close) {
+                    Ok((_, closed)) => (value, closed),
+                    Err(err) => {
+                        self.report(err)?;
+                        (value, self.resync(depth, &close)?.1)
                     }
-                    "return" => (),
-                    _ => return Err(E::new(p, K::UnexpectedIdentifier(x))),
-                }
-            }
-            T::OpenSet => {
-                if pair_set {
-                    return Err(E::new(p, K::MissingComma));
-                }
-                if !first_open {
-                    let mut old_current: Vec<KeyValuePair> = Vec::new();
-                    swap(&mut old_current, &mut current);
-                    let mut old_pair_key = None;
-                    swap(&mut old_pair_key, &mut pair_key);
-                    data.push((old_pair_key, old_current));
-                    pair_value = V::Null;
-                } else {
-                    first_open = false;
-                }
-            }
-            T::Separator => {
-                println!("zeh");
-                if pair_set {
-                    let mut old_pair_key = None;
-                    let mut old_pair_value = V::Null;
-                    swap(&mut old_pair_key, &mut pair_key);
-                    swap(&mut old_pair_value, &mut pair_value);
-                    current.push(KeyValuePair::new(old_pair_key, old_pair_value));
-                    pair_set = false;
-                }
-            }
-            T::CloseSet => {
-                if pair_set {
-                    let mut old_pair_key = None;
-                    let mut old_pair_value = V::Null;
-                    swap(&mut old_pair_key, &mut pair_key);
-                    swap(&mut old_pair_value, &mut pair_value);
-                    current.push(KeyValuePair::new(old_pair_key, old_pair_value));
-                    pair_set = false;
-                }
-
-                if data.len() == 0 {
-                    lexer_break = true;
-                } else {
-                    if pair_set {
-                        let mut old_pair_key = None;
-                        let mut old_pair_value = V::Null;
-                        swap(&mut old_pair_key, &mut pair_key);
-                        swap(&mut old_pair_value, &mut pair_value);
-                        current.push(KeyValuePair::new(old_pair_key, old_pair_value));
-                        pair_set = false;
+                },
+                Err(err) => {
+                    self.report(err)?;
+                    let (end, closed) = self.resync(depth, &close)?;
+                    let span = Span::new(value_start, end.max(value_start));
+                    let text = self.source[span.start.index..span.end.index].to_string();
+                    (Value::Error(Expr::new(text, span)), closed)
+                }
+            };
+            let key = match key {
+                Some(key) => {
+                    next.observe(&key);
+                    key
+                }
+                None => next.take(),
+            };
+            match seen.get(&key) {
+                Some(&(i, first)) => {
+                    if self.options.strict_keys {
+                        let kind = DeserErrorKind::DuplicateKey(key, first);
+                        self.report(DeserError::new(start, kind))?;
                     }
-                    let (old_key, mut old_current) = data.pop().unwrap();
-                    swap(&mut old_current, &mut current);
-                    current.push(KeyValuePair::new(old_key, V::Set(old_current)));
+                    current[i].value = value;
+                }
+                None => {
+                    seen.insert(key.clone(), (current.len(), start));
+                    current.push(KeyValuePair::new(key, value));
                 }
             }
+            if closed {
+                return Ok(current);
+            }
         }
-        if lexer_break {
-            break;
+    }
+
+    /// Parses a set element and its key, if it has one.
+    ///
+    /// The key is stored as soon as it is read, along with where the value
+    /// starts, so that they survive errors in the value.
+    fn element(
+        &mut self,
+        key: &mut Option<Key>,
+        start: &mut Position,
+    ) -> Result<Value, DeserError> {
+        let mut value = self.value()?.1;
+        if let Some(x) = self.key(&value)? {
+            *key = Some(x);
+            *start = self.end;
+            if let Some((span, _)) = self.peek()? {
+                *start = span.start;
+            }
+            value = self.value()?.1;
+        }
+        Ok(value)
+    }
+
+    /// Consumes the `=>` after a key, returning the key if there was one.
+    pub(crate) fn key(&mut self, value: &Value) -> Result<Option<Key>, DeserError> {
+        let p = match self.peek()? {
+            Some((span, Token::Assignment)) => span.start,
+            _ => return Ok(None),
+        };
+        match Key::from_value(value) {
+            Some(key) => {
+                self.next()?;
+                Ok(Some(key))
+            }
+            None => Err(DeserError::new(p, DeserErrorKind::InvalidKey)),
+        }
+    }
+
+    /// Consumes the comma or closing token after a set element.
+    ///
+    /// Returns the token span, and `true` if it closed the set.
+    pub(crate) fn element_end(&mut self, close: &Token) -> Result<(Span, bool), DeserError> {
+        use DeserError as E;
+        use DeserErrorKind as K;
+        use Token as T;
+        if self.errors.is_some() {
+            match self.peek()? {
+                Some((_, T::Separator))
+                | Some((_, T::Assignment))
+                | Some((_, T::CloseSet))
+                | Some((_, T::CloseParen))
+                | Some((_, T::CloseBrace))
+                | Some((_, T::Terminator))
+                | None => (),
+                Some((span, _)) => {
+                    let span = *span;
+                    self.report(E::new(span.start, K::MissingComma))?;
+                    return Ok((span, false));
+                }
+            }
         }
+        match self.expect()? {
+            (span, T::Separator) => Ok((span, false)),
+            (span, t) if t == *close => Ok((span, true)),
+            (span, T::Assignment) => Err(E::new(span.start, K::InvalidKey)),
+            (span, t @ T::CloseSet) | (span, t @ T::CloseParen) => {
+                Err(E::new(span.start, K::UnexpectedToken(t)))
+            }
+            (span, _) => Err(E::new(span.start, K::MissingComma)),
+        }
+    }
+}
+
+/// Converts an integer literal, overflowing into a float the way PHP does.
+pub(crate) fn int_value(raw: &str) -> Result<Value, DeserErrorKind> {
+    let digits: String = raw.chars().filter(|&c| c != '_').collect();
+    let (radix, body) = match digits.as_bytes() {
+        [b'0', b'x', ..] | [b'0', b'X', ..] => (16, &digits[2..]),
+        [b'0', b'o', ..] | [b'0', b'O', ..] => (8, &digits[2..]),
+        [b'0', b'b', ..] | [b'0', b'B', ..] => (2, &digits[2..]),
+        [b'0', _, ..] => (8, &digits[1..]),
+        _ => (10, &digits[..]),
+    };
+    match i64::from_str_radix(body, radix) {
+        Ok(v) => Ok(Value::Int(v)),
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow && radix == 10 => {
+            float_value(body).map_err(|_| DeserErrorKind::IntCast(raw.to_string()))
+        }
+        Err(e) if *e.kind() == IntErrorKind::PosOverflow => {
+            let v = body.chars().try_fold(0.0, |acc: f64, c| {
+                c.to_digit(radix)
+                    .map(|d| acc * f64::from(radix) + f64::from(d))
+            });
+            v.map(Value::Float)
+                .ok_or_else(|| DeserErrorKind::IntCast(raw.to_string()))
+        }
+        Err(_) => Err(DeserErrorKind::IntCast(raw.to_string())),
+    }
+}
+
+/// Converts a floating point literal.
+pub(crate) fn float_value(raw: &str) -> Result<Value, DeserErrorKind> {
+    match f64::from_str(&raw.replace('_', "")) {
+        Ok(v) => Ok(Value::Float(v)),
+        Err(_) => Err(DeserErrorKind::FloatCast(raw.to_string())),
     }
-    Ok(current)
 }
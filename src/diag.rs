@@ -0,0 +1,224 @@
+use crate::deser::{DeserError, DeserErrorKind};
+use crate::lex::{LexError, LexErrorKind, Lexer, Position};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::Path;
+
+/// Rendering of diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum DiagnosticStyle {
+    /// Plain text.
+    #[default]
+    Plain,
+
+    /// Text colored with ANSI escape codes, for terminals.
+    Ansi,
+}
+
+/// An error rendered like rustc does, with the offending line of source.
+///
+/// ```text
+/// error: missing comma
+///  --> config/app.php:3:5
+///   |
+/// 3 |     'b' => 2,
+///   |     ^^^ expected `,` before this
+///   |
+///   = help: missing `,` between elements
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic<'a> {
+    message: String,
+    label: String,
+    help: Option<String>,
+    position: Position,
+    end: usize,
+    source: Option<&'a str>,
+    path: Option<&'a Path>,
+    style: DiagnosticStyle,
+}
+
+impl<'a> Diagnostic<'a> {
+    fn new(
+        kind: &DeserErrorKind,
+        position: Position,
+        source: Option<&'a str>,
+        path: Option<&'a Path>,
+        style: DiagnosticStyle,
+    ) -> Self {
+        let (label, help) = notes(kind);
+        let end = match (kind, source) {
+            (DeserErrorKind::Lex(_), _) | (_, None) => position.index,
+            (_, Some(source)) => token_end(source, position.index),
+        };
+        Self {
+            message: kind.to_string(),
+            label,
+            help,
+            position,
+            end,
+            source,
+            path,
+            style,
+        }
+    }
+
+    fn paint(&self, f: &mut Formatter<'_>, color: &str, text: &str) -> Result<(), FmtError> {
+        match self.style {
+            DiagnosticStyle::Plain => write!(f, "{}", text),
+            DiagnosticStyle::Ansi => write!(f, "\x1b[{}m{}\x1b[0m", color, text),
+        }
+    }
+}
+
+const RED: &str = "1;31";
+const BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let line = (self.position.line + 1).to_string();
+        let pad = " ".repeat(line.len());
+        self.paint(f, RED, "error")?;
+        self.paint(f, BOLD, &format!(": {}", self.message))?;
+        writeln!(f)?;
+        self.paint(f, BLUE, &format!("{}--> ", pad))?;
+        let path = self.path.map(|x| x.display().to_string());
+        writeln!(
+            f,
+            "{}:{}:{}",
+            path.as_deref().unwrap_or("<string>"),
+            line,
+            self.position.column + 1
+        )?;
+        if let Some(source) = self.source {
+            let index = self.position.index.min(source.len());
+            let start = source[..index].rfind('\n').map_or(0, |i| i + 1);
+            let end = source[index..]
+                .find('\n')
+                .map_or(source.len(), |i| index + i);
+            let text = source[start..end].trim_end_matches('\r');
+            let token_end = self.end.clamp(index, start + text.len());
+            let offset = width(&source[start..index]);
+            let carets = width(&source[index..token_end]).max(1);
+            self.paint(f, BLUE, &format!("{} |", pad))?;
+            writeln!(f)?;
+            self.paint(f, BLUE, &format!("{} |", line))?;
+            writeln!(f, " {}", text.replace('\t', "    "))?;
+            self.paint(f, BLUE, &format!("{} |", pad))?;
+            write!(f, " {}", " ".repeat(offset))?;
+            let underline = format!("{} {}", "^".repeat(carets), self.label);
+            self.paint(f, RED, underline.trim_end())?;
+            writeln!(f)?;
+        }
+        if let Some(help) = &self.help {
+            self.paint(f, BLUE, &format!("{} |", pad))?;
+            writeln!(f)?;
+            self.paint(f, BLUE, &format!("{} =", pad))?;
+            write!(f, " ")?;
+            self.paint(f, BOLD, "help")?;
+            writeln!(f, ": {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+/// Display width of a piece of a line, with tabs as four columns.
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\t' => 4,
+            '\u{FEFF}' => 0,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// End of the token starting at an index, for underlining it.
+fn token_end(source: &str, index: usize) -> usize {
+    let token = source
+        .get(index..)
+        .and_then(|rest| Lexer::tokens(rest).next());
+    match token {
+        Some(Ok((span, _))) if span.start.index == 0 => index + span.end.index,
+        _ => index,
+    }
+}
+
+/// Label under the offending token and help note for an error kind.
+fn notes(kind: &DeserErrorKind) -> (String, Option<String>) {
+    use DeserErrorKind as K;
+    use LexErrorKind as L;
+    let (label, help) = match kind {
+        K::Lex(L::EOF) => (
+            "file ends here",
+            Some("a string, comment or number is not closed"),
+        ),
+        K::Lex(L::Unexpected(_)) => ("unexpected character", None),
+        K::Lex(L::InvalidUnicode(_)) => (
+            "invalid escape",
+            Some("codepoints must be at most U+10FFFF and not surrogates"),
+        ),
+        K::Lex(L::InvalidIndentation) => (
+            "indented less than the closing marker",
+            Some("indent every line of a heredoc at least as far as its closing marker"),
+        ),
+        K::UnexpectedIdentifier(_) => ("unexpected identifier", None),
+        K::InvalidKey => (
+            "cannot be used as a key",
+            Some("keys must be strings, integers, floats, booleans or null"),
+        ),
+        K::MissingComma => (
+            "expected `,` before this",
+            Some("missing `,` between elements"),
+        ),
+        K::FloatCast(_) | K::IntCast(_) => ("invalid number", None),
+        K::UnexpectedOperator(_) => ("unexpected operator", None),
+        K::UnexpectedToken(_) => ("unexpected token", None),
+        K::InvalidRoot => (
+            "expected `return`",
+            Some("the file must return a set, like `return [];`"),
+        ),
+        K::InvalidRootVariable(name) => {
+            let label = format!("expected `${} = [...]`", name);
+            let help = format!(
+                "the file must assign a set to `${0}`, like `${0} = [];`",
+                name
+            );
+            return (label, Some(help));
+        }
+        K::InvalidArguments(_) => ("invalid arguments", None),
+        K::InvalidPath(_) => ("not a set", None),
+        K::DuplicateKey(..) => (
+            "repeated here",
+            Some("remove one of the elements, or turn off `strict_keys`"),
+        ),
+        K::Custom(_) | K::Io(..) => ("", None),
+        K::InvalidUtf8 => (
+            "invalid UTF-8",
+            Some("legacy files can be read with `Encoding::Latin1`"),
+        ),
+    };
+    (label.to_string(), help.map(str::to_string))
+}
+
+impl DeserError {
+    /// Renders the error with the line it occurred on, if the source is
+    /// known.
+    pub fn diagnostic(&self, style: DiagnosticStyle) -> Diagnostic<'_> {
+        Diagnostic::new(
+            &self.kind,
+            self.position,
+            self.source_text(),
+            self.path(),
+            style,
+        )
+    }
+}
+
+impl LexError {
+    /// Renders the error with the line of source it occurred on.
+    pub fn diagnostic<'a>(&self, source: &'a str, style: DiagnosticStyle) -> Diagnostic<'a> {
+        let kind = DeserErrorKind::Lex(self.kind);
+        Diagnostic::new(&kind, self.position, Some(source), None, style)
+    }
+}
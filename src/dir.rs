@@ -0,0 +1,90 @@
+use crate::deser::{deser_file_with, DeserError, DeserErrorKind, DeserOptions};
+use crate::kvp::{Key, KeyValuePair, Value};
+use crate::lex::Position;
+use crate::ser::{ser_file_with, SerOptions};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Deserialize a directory of files, one per top-level key, like Laravel's
+/// `config` directory.
+pub fn load_dir<P: AsRef<Path>>(path: P) -> Result<Vec<KeyValuePair>, DeserError> {
+    load_dir_with(path, &DeserOptions::default())
+}
+
+/// Deserialize a directory of files with options.
+///
+/// Every `*.php` file in the directory is read, in order of its name, and
+/// nested under its file stem. Stems are normalized like PHP keys, so `0.php`
+/// is read as the integer key `0`, and stems that are not valid UTF-8 are an
+/// [`InvalidKey`](DeserErrorKind::InvalidKey) error. Subdirectories are not
+/// read.
+pub fn load_dir_with<P: AsRef<Path>>(
+    path: P,
+    options: &DeserOptions<'_>,
+) -> Result<Vec<KeyValuePair>, DeserError> {
+    let path = path.as_ref();
+    let io = |err: IoError| DeserError::from(err).with_path(path);
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).map_err(io)? {
+        let file = entry.map_err(io)?.path();
+        if file.extension().is_some_and(|x| x == "php") && file.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+    files
+        .into_iter()
+        .map(|file| {
+            let stem = file
+                .file_stem()
+                .unwrap_or_default()
+                .to_str()
+                .ok_or_else(|| {
+                    DeserError::new(Position::default(), DeserErrorKind::InvalidKey)
+                        .with_path(&file)
+                })?;
+            Ok(KeyValuePair::new(
+                Key::from(stem),
+                Value::Set(deser_file_with(&file, options)?),
+            ))
+        })
+        .collect()
+}
+
+/// Serialize to a directory, one file per top-level key.
+pub fn ser_dir<P: AsRef<Path>>(output: &[KeyValuePair], path: P) -> std::io::Result<()> {
+    ser_dir_with(output, path, &SerOptions::default())
+}
+
+/// Serialize to a directory with options.
+///
+/// Each top-level key is written to `<key>.php`, which returns its value.
+/// The directory is created if needed. Values other than sets, and keys that
+/// are not plain file names, are rejected before anything is written.
+pub fn ser_dir_with<P: AsRef<Path>>(
+    output: &[KeyValuePair],
+    path: P,
+    options: &SerOptions,
+) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let invalid = |message: String| IoError::new(ErrorKind::InvalidInput, message);
+    let mut files: Vec<(PathBuf, &[KeyValuePair])> = Vec::new();
+    for kvp in output {
+        let name = match &kvp.key {
+            Key::Int(x) => x.to_string(),
+            Key::Str(x) => x.clone(),
+        };
+        if name.is_empty() || name.starts_with('.') || name.contains(&['/', '\\'][..]) {
+            return Err(invalid(format!("`{}` is not a file name", name)));
+        }
+        match &kvp.value {
+            Value::Set(items) => files.push((path.join(format!("{}.php", name)), items)),
+            _ => return Err(invalid(format!("`{}` is not a set", name))),
+        }
+    }
+    std::fs::create_dir_all(path)?;
+    for (file, items) in files {
+        ser_file_with(items, file, options)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use crate::kvp::{php_str, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Error as FmtError, Formatter};
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Source of environment variables for evaluating `env()` calls.
+pub trait EnvSource: Debug {
+    /// Looks up a variable.
+    fn var(&self, name: &str) -> Option<String>;
+}
+
+/// Environment of the current process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessEnv;
+
+impl EnvSource for ProcessEnv {
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+impl EnvSource for HashMap<String, String> {
+    fn var(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+/// Variables read from a `.env` file.
+///
+/// Lines are `NAME=value` pairs, optionally prefixed by `export`. Values may
+/// be single quoted, double quoted with escapes, or bare with a trailing
+/// `# comment`. Blank lines, comments and malformed lines are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DotEnv {
+    /// The parsed variables.
+    pub vars: HashMap<String, String>,
+}
+
+impl DotEnv {
+    /// Parses the contents of a `.env` file.
+    pub fn parse(input: &str) -> Self {
+        let mut vars = HashMap::new();
+        for line in input.lines() {
+            let line = line.trim();
+            let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => continue,
+            };
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                continue;
+            }
+            vars.insert(name.to_string(), dotenv_value(value));
+        }
+        Self { vars }
+    }
+
+    /// Reads and parses a `.env` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self::parse(&read_to_string(path)?))
+    }
+}
+
+impl EnvSource for DotEnv {
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(name).cloned()
+    }
+}
+
+fn dotenv_value(raw: &str) -> String {
+    if let Some(rest) = raw.strip_prefix('\'') {
+        return match rest.find('\'') {
+            Some(i) => rest[..i].to_string(),
+            None => rest.to_string(),
+        };
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => value.push('\\'),
+                },
+                c => value.push(c),
+            }
+        }
+        return value;
+    }
+    match raw.find(" #") {
+        Some(i) => raw[..i].trim_end().to_string(),
+        None => raw.to_string(),
+    }
+}
+
+/// Handling of `env()` calls during deserialization.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum EnvMode<'a> {
+    /// Keep calls unevaluated as [`Value::Env`] nodes.
+    #[default]
+    Keep,
+
+    /// Evaluate calls against a source.
+    Evaluate(&'a dyn EnvSource),
+}
+
+/// A `filter_var()` validation filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filter {
+    /// `FILTER_VALIDATE_BOOLEAN`
+    Bool,
+
+    /// `FILTER_VALIDATE_INT`
+    Int,
+
+    /// `FILTER_VALIDATE_FLOAT`
+    Float,
+}
+
+impl Filter {
+    /// Looks up a filter by its constant name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "FILTER_VALIDATE_BOOLEAN" | "FILTER_VALIDATE_BOOL" => Some(Self::Bool),
+            "FILTER_VALIDATE_INT" => Some(Self::Int),
+            "FILTER_VALIDATE_FLOAT" => Some(Self::Float),
+            _ => None,
+        }
+    }
+
+    /// Name of the filter constant.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bool => "FILTER_VALIDATE_BOOLEAN",
+            Self::Int => "FILTER_VALIDATE_INT",
+            Self::Float => "FILTER_VALIDATE_FLOAT",
+        }
+    }
+
+    /// Applies the filter like `filter_var()` without flags does.
+    pub fn apply(self, value: &Value) -> Value {
+        use Value as V;
+        let s = match value {
+            V::Null | V::Bool(false) => String::new(),
+            V::Bool(true) => "1".to_string(),
+            V::Int(x) => x.to_string(),
+            V::Float(x) => x.to_string(),
+            V::Str(x) => x.trim().to_string(),
+            _ => return V::Bool(false),
+        };
+        match self {
+            Self::Bool => V::Bool(matches!(
+                s.to_lowercase().as_str(),
+                "1" | "true" | "on" | "yes"
+            )),
+            Self::Int => {
+                let digits = s.strip_prefix(|c| c == '-' || c == '+').unwrap_or(&s);
+                let valid = digits == "0"
+                    || (!digits.starts_with('0')
+                        && !digits.is_empty()
+                        && digits.bytes().all(|b| b.is_ascii_digit()));
+                match s.parse() {
+                    Ok(x) if valid => V::Int(x),
+                    _ => V::Bool(false),
+                }
+            }
+            Self::Float => match s.parse() {
+                Ok(x)
+                    if s.bytes()
+                        .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b)) =>
+                {
+                    V::Float(x)
+                }
+                _ => V::Bool(false),
+            },
+        }
+    }
+}
+
+/// An unevaluated `env()` call.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Env {
+    /// Name of the variable.
+    pub name: String,
+
+    /// Default value, if one was given.
+    pub default: Option<Box<Value>>,
+
+    /// Filter applied to the result with `filter_var()`, if any.
+    pub filter: Option<Filter>,
+}
+
+impl Env {
+    /// Creates a new call.
+    pub fn new(name: String, default: Option<Value>) -> Self {
+        Self {
+            name,
+            default: default.map(Box::new),
+            filter: None,
+        }
+    }
+
+    /// Evaluates the call against a source.
+    pub fn evaluate(&self, source: &dyn EnvSource) -> Value {
+        let value = match source.var(&self.name) {
+            Some(x) => Value::Str(x),
+            None => match &self.default {
+                Some(x) => (**x).clone(),
+                None => Value::Null,
+            },
+        };
+        match self.filter {
+            Some(filter) => filter.apply(&value),
+            None => value,
+        }
+    }
+}
+
+impl Display for Env {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        if self.filter.is_some() {
+            write!(f, "filter_var(")?;
+        }
+        write!(f, "env({}", php_str(&self.name))?;
+        if let Some(x) = &self.default {
+            write!(f, ", {}", x)?;
+        }
+        write!(f, ")")?;
+        if let Some(filter) = self.filter {
+            write!(f, ", {})", filter.name())?;
+        }
+        Ok(())
+    }
+}
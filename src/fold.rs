@@ -0,0 +1,505 @@
+use crate::constant::ConstantTable;
+use crate::deser::{float_value, int_value};
+use crate::kvp::Value;
+use crate::lex::{Lexer, Token};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+/// Binding power of prefix operators other than `!`.
+const UNARY: u8 = 35;
+
+/// Type names that make `(name)` a cast rather than a parenthesized
+/// constant.
+const CASTS: &[&str] = &[
+    "int", "integer", "bool", "boolean", "float", "double", "real", "string", "array", "object",
+    "unset", "binary",
+];
+
+/// Evaluates a constant expression the way PHP does.
+///
+/// Returns `None` if any part of it is not constant, or if PHP would throw
+/// while evaluating it. Identifiers are looked up in `constants`.
+pub(crate) fn fold(text: &str, constants: Option<&ConstantTable>) -> Option<Value> {
+    let tokens = Lexer::tokens(text)
+        .map(|x| x.map(|(_, token)| token))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    let mut folder = Folder {
+        tokens: &tokens,
+        index: 0,
+        constants,
+    };
+    let value = folder.expr(0)?;
+    match folder.index == tokens.len() {
+        true => Some(value),
+        false => None,
+    }
+}
+
+/// Precedence climbing over the tokens of an expression.
+struct Folder<'a> {
+    tokens: &'a [Token],
+    index: usize,
+    constants: Option<&'a ConstantTable>,
+}
+
+impl Folder<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.index)?;
+        self.index += 1;
+        Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> Option<()> {
+        match self.next() {
+            Some(x) if x == token => Some(()),
+            _ => None,
+        }
+    }
+
+    /// Parses operators binding tighter than `min`.
+    fn expr(&mut self, min: u8) -> Option<Value> {
+        let mut lhs = self.prefix()?;
+        loop {
+            let op = match self.tokens.get(self.index) {
+                Some(Token::Operator(x)) => x.clone(),
+                Some(Token::Identifier(x)) => x.to_lowercase(),
+                _ => break,
+            };
+            let (left, right) = match infix(&op) {
+                Some(x) => x,
+                None => break,
+            };
+            if left < min {
+                break;
+            }
+            self.index += 1;
+            lhs = match op.as_str() {
+                "?" => {
+                    let colon = Token::Operator(":".to_string());
+                    let then = match self.tokens.get(self.index) {
+                        Some(x) if *x == colon => None,
+                        _ => Some(self.expr(0)?),
+                    };
+                    self.eat(&colon)?;
+                    let other = self.expr(right)?;
+                    match truthy(&lhs)? {
+                        true => then.unwrap_or(lhs),
+                        false => other,
+                    }
+                }
+                _ => binary(&op, lhs, self.expr(right)?)?,
+            };
+        }
+        Some(lhs)
+    }
+
+    fn prefix(&mut self) -> Option<Value> {
+        use Token as T;
+        use Value as V;
+        let constants = self.constants;
+        let value = match self.next()? {
+            T::Int(x) => int_value(x).ok()?,
+            T::Float(x) => float_value(x).ok()?,
+            T::SingleQuote(x) | T::DoubleQuote(x) => V::Str(x.clone()),
+            T::Bytes(x) => V::Bytes(x.clone()),
+            T::Identifier(x) => match x.to_lowercase().as_str() {
+                "true" => V::Bool(true),
+                "false" => V::Bool(false),
+                "null" => V::Null,
+                _ => constants?.get(x)?.clone(),
+            },
+            T::OpenParen => match (self.tokens.get(self.index), self.tokens.get(self.index + 1)) {
+                (Some(T::Identifier(x)), Some(T::CloseParen))
+                    if CASTS.contains(&x.to_lowercase().as_str()) =>
+                {
+                    let cast = x.to_lowercase();
+                    self.index += 2;
+                    return self.expr(UNARY).and_then(|x| cast_value(&cast, x));
+                }
+                _ => {
+                    let value = self.expr(0)?;
+                    self.eat(&T::CloseParen)?;
+                    value
+                }
+            },
+            T::Operator(x) => match x.as_str() {
+                "-" => binary("*", self.expr(UNARY)?, V::Int(-1))?,
+                "+" => binary("*", self.expr(UNARY)?, V::Int(1))?,
+                "!" => V::Bool(!truthy(&self.expr(33)?)?),
+                "~" => not(self.expr(UNARY)?)?,
+                "@" => self.expr(UNARY)?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Left and right binding power of an infix operator.
+fn infix(op: &str) -> Option<(u8, u8)> {
+    let power = match op {
+        "or" => (1, 2),
+        "xor" => (3, 4),
+        "and" => (5, 6),
+        "?" => (7, 8),
+        "??" => (10, 9),
+        "||" => (11, 12),
+        "&&" => (13, 14),
+        "|" => (15, 16),
+        "^" => (17, 18),
+        "&" => (19, 20),
+        "==" | "!=" | "===" | "!==" | "<>" | "<=>" => (21, 22),
+        "<" | "<=" | ">" | ">=" => (23, 24),
+        "." => (25, 26),
+        "<<" | ">>" => (27, 28),
+        "+" | "-" => (29, 30),
+        "*" | "/" | "%" => (31, 32),
+        "**" => (38, 37),
+        _ => return None,
+    };
+    Some(power)
+}
+
+/// Applies a binary operator to constant operands.
+pub(crate) fn binary(op: &str, a: Value, b: Value) -> Option<Value> {
+    use Value as V;
+    let value = match op {
+        "or" | "||" => V::Bool(truthy(&a)? || truthy(&b)?),
+        "and" | "&&" => V::Bool(truthy(&a)? && truthy(&b)?),
+        "xor" => V::Bool(truthy(&a)? != truthy(&b)?),
+        "??" => match a {
+            V::Null => b,
+            a => a,
+        },
+        "===" => V::Bool(identical(&a, &b)?),
+        "!==" => V::Bool(!identical(&a, &b)?),
+        "==" => V::Bool(compare(&a, &b)? == Ordering::Equal),
+        "!=" | "<>" => V::Bool(compare(&a, &b)? != Ordering::Equal),
+        "<" => V::Bool(compare(&a, &b)? == Ordering::Less),
+        "<=" => V::Bool(compare(&a, &b)? != Ordering::Greater),
+        ">" => V::Bool(compare(&a, &b)? == Ordering::Greater),
+        ">=" => V::Bool(compare(&a, &b)? != Ordering::Less),
+        "<=>" => V::Int(compare(&a, &b)? as i64),
+        "." => {
+            let mut x = string(&a)?;
+            x.extend_from_slice(&string(&b)?);
+            bytes_value(x)
+        }
+        "|" | "&" | "^" => match (bytes(&a), bytes(&b)) {
+            (Some(x), Some(y)) => bytes_value(bitwise_bytes(op, x, y)),
+            _ => {
+                let (x, y) = (int(&a)?, int(&b)?);
+                V::Int(match op {
+                    "|" => x | y,
+                    "&" => x & y,
+                    _ => x ^ y,
+                })
+            }
+        },
+        "<<" | ">>" => {
+            let (x, y) = (int(&a)?, int(&b)?);
+            V::Int(match (op, y) {
+                (_, y) if y < 0 => return None,
+                ("<<", y) if y >= 64 => 0,
+                ("<<", y) => x << y,
+                (_, y) => x >> y.min(63),
+            })
+        }
+        "%" => {
+            let (x, y) = (int(&a)?, int(&b)?);
+            match y {
+                0 => return None,
+                y => V::Int(x.wrapping_rem(y)),
+            }
+        }
+        _ => arithmetic(op, number(&a)?, number(&b)?)?,
+    };
+    Some(value)
+}
+
+/// A value converted for arithmetic.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn float(self) -> f64 {
+        match self {
+            Number::Int(x) => x as f64,
+            Number::Float(x) => x,
+        }
+    }
+}
+
+fn arithmetic(op: &str, a: Number, b: Number) -> Option<Value> {
+    use Number as N;
+    let value = match (a, b) {
+        (N::Int(x), N::Int(y)) => {
+            let exact = match op {
+                "+" => x.checked_add(y),
+                "-" => x.checked_sub(y),
+                "*" => x.checked_mul(y),
+                "/" if y == 0 => return None,
+                "/" => x.checked_rem(y).filter(|&r| r == 0).map(|_| x / y),
+                "**" => u32::try_from(y).ok().and_then(|y| x.checked_pow(y)),
+                _ => return None,
+            };
+            match exact {
+                Some(x) => Value::Int(x),
+                None => Value::Float(float_arithmetic(op, a.float(), b.float())?),
+            }
+        }
+        (a, b) => Value::Float(float_arithmetic(op, a.float(), b.float())?),
+    };
+    Some(value)
+}
+
+fn float_arithmetic(op: &str, x: f64, y: f64) -> Option<f64> {
+    let value = match op {
+        "+" => x + y,
+        "-" => x - y,
+        "*" => x * y,
+        "/" if y == 0.0 => return None,
+        "/" => x / y,
+        "**" => x.powf(y),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn not(a: Value) -> Option<Value> {
+    match a {
+        Value::Int(x) => Some(Value::Int(!x)),
+        Value::Float(_) => Some(Value::Int(!int(&a)?)),
+        a => Some(bytes_value(bytes(&a)?.iter().map(|x| !x).collect())),
+    }
+}
+
+/// Applies a bitwise operator to strings byte by byte.
+fn bitwise_bytes(op: &str, x: &[u8], y: &[u8]) -> Vec<u8> {
+    let (short, long) = match x.len() <= y.len() {
+        true => (x, y),
+        false => (y, x),
+    };
+    let mut out: Vec<u8> = short
+        .iter()
+        .zip(long)
+        .map(|(a, b)| match op {
+            "|" => a | b,
+            "&" => a & b,
+            _ => a ^ b,
+        })
+        .collect();
+    if op == "|" {
+        out.extend_from_slice(&long[short.len()..]);
+    }
+    out
+}
+
+fn cast_value(cast: &str, a: Value) -> Option<Value> {
+    let value = match cast {
+        "int" | "integer" => Value::Int(int(&a)?),
+        "float" | "double" => Value::Float(number(&a)?.float()),
+        "string" | "binary" => bytes_value(string(&a)?),
+        "bool" | "boolean" => Value::Bool(truthy(&a)?),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn bytes(a: &Value) -> Option<&[u8]> {
+    match a {
+        Value::Str(x) => Some(x.as_bytes()),
+        Value::Bytes(x) => Some(x),
+        _ => None,
+    }
+}
+
+fn bytes_value(x: Vec<u8>) -> Value {
+    match String::from_utf8(x) {
+        Ok(x) => Value::Str(x),
+        Err(e) => Value::Bytes(e.into_bytes()),
+    }
+}
+
+/// Converts to boolean, `None` for values only known at runtime.
+fn truthy(a: &Value) -> Option<bool> {
+    let value = match a {
+        Value::Null => false,
+        Value::Bool(x) => *x,
+        Value::Int(x) => *x != 0,
+        Value::Float(x) => *x != 0.0,
+        Value::Str(_) | Value::Bytes(_) => !matches!(bytes(a)?, b"" | b"0"),
+        Value::Set(x) => !x.is_empty(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Converts to a string, which PHP does not do for sets.
+fn string(a: &Value) -> Option<Vec<u8>> {
+    let value = match a {
+        Value::Null | Value::Bool(false) => vec![],
+        Value::Bool(true) => b"1".to_vec(),
+        Value::Int(x) => x.to_string().into_bytes(),
+        Value::Float(x) => float_string(*x).into_bytes(),
+        Value::Str(_) | Value::Bytes(_) => bytes(a)?.to_vec(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Formats a float like PHP 8 does, with the 14 significant digits of its
+/// default `precision`.
+fn float_string(x: f64) -> String {
+    if x.is_nan() {
+        return "NAN".to_string();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "INF" } else { "-INF" }.to_string();
+    }
+    if x == 0.0 {
+        return if x.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    let sci = format!("{:.13e}", x.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    let digits = mantissa.replace('.', "");
+    let digits = digits.trim_end_matches('0');
+    let sign = if x < 0.0 { "-" } else { "" };
+    match exp {
+        -4..=-1 => format!("{}0.{}{}", sign, "0".repeat((-exp - 1) as usize), digits),
+        0..=13 => {
+            let point = exp as usize + 1;
+            match digits.len() > point {
+                true => format!("{}{}.{}", sign, &digits[..point], &digits[point..]),
+                false => format!("{}{:0<width$}", sign, digits, width = point),
+            }
+        }
+        _ => {
+            let (first, rest) = digits.split_at(1);
+            let rest = if rest.is_empty() { "0" } else { rest };
+            let exp_sign = if exp < 0 { '-' } else { '+' };
+            format!("{}{}.{}E{}{}", sign, first, rest, exp_sign, exp.abs())
+        }
+    }
+}
+
+/// Converts to a number for arithmetic, `None` where PHP throws a
+/// `TypeError`.
+fn number(a: &Value) -> Option<Number> {
+    let value = match a {
+        Value::Null => Number::Int(0),
+        Value::Bool(x) => Number::Int(*x as i64),
+        Value::Int(x) => Number::Int(*x),
+        Value::Float(x) => Number::Float(*x),
+        Value::Str(_) | Value::Bytes(_) => numeric(bytes(a)?)?.0,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn int(a: &Value) -> Option<i64> {
+    match number(a)? {
+        Number::Int(x) => Some(x),
+        Number::Float(x) if x.is_finite() && x.abs() < 9.223_372_036_854_775e18 => Some(x as i64),
+        Number::Float(_) => None,
+    }
+}
+
+/// Parses the leading number of a string, and whether the whole string is
+/// numeric.
+fn numeric(text: &[u8]) -> Option<(Number, bool)> {
+    let space = |c: &u8| b" \t\n\r\x0b\x0c".contains(c);
+    let start = text.iter().position(|c| !space(c)).unwrap_or(text.len());
+    let digits = |i: usize| {
+        text[i..]
+            .iter()
+            .position(|c| !c.is_ascii_digit())
+            .map_or(text.len(), |n| i + n)
+    };
+    let mut end = start;
+    if let Some(b'+') | Some(b'-') = text.get(end) {
+        end += 1;
+    }
+    let whole = digits(end);
+    let mut float = false;
+    let integer = whole > end;
+    end = whole;
+    if text.get(end) == Some(&b'.') && (integer || digits(end + 1) > end + 1) {
+        float = true;
+        end = digits(end + 1);
+    }
+    if !integer && !float {
+        return None;
+    }
+    if let Some(b'e') | Some(b'E') = text.get(end) {
+        let mut exp = end + 1;
+        if let Some(b'+') | Some(b'-') = text.get(exp) {
+            exp += 1;
+        }
+        if digits(exp) > exp {
+            float = true;
+            end = digits(exp);
+        }
+    }
+    let rest = text[end..].iter().all(space);
+    let raw = std::str::from_utf8(&text[start..end]).ok()?;
+    let value = match raw.parse::<i64>() {
+        Ok(x) if !float => Number::Int(x),
+        _ => Number::Float(raw.parse().ok()?),
+    };
+    Some((value, rest))
+}
+
+/// Whether two values are the same type and value, as `===` checks.
+fn identical(a: &Value, b: &Value) -> Option<bool> {
+    use Value as V;
+    let value = match (a, b) {
+        (V::Null, V::Null) => true,
+        (V::Bool(x), V::Bool(y)) => x == y,
+        (V::Int(x), V::Int(y)) => x == y,
+        (V::Float(x), V::Float(y)) => x == y,
+        (V::Str(_), _) | (V::Bytes(_), _) if bytes(b).is_some() => bytes(a) == bytes(b),
+        (V::Set(_), _) | (_, V::Set(_)) => return None,
+        _ => false,
+    };
+    Some(value)
+}
+
+/// Compares two values with PHP 8's loose comparison rules.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    use Value as V;
+    match (a, b) {
+        (V::Set(_), _) | (_, V::Set(_)) => None,
+        (V::Null, _) | (_, V::Null) if bytes(a).is_some() || bytes(b).is_some() => {
+            Some(string(a)?.cmp(&string(b)?))
+        }
+        (V::Null, _) | (_, V::Null) | (V::Bool(_), _) | (_, V::Bool(_)) => {
+            Some(truthy(a)?.cmp(&truthy(b)?))
+        }
+        _ => match (bytes(a), bytes(b)) {
+            (Some(x), Some(y)) => match (numeric(x), numeric(y)) {
+                (Some((x, true)), Some((y, true))) => compare_numbers(x, y),
+                _ => Some(x.cmp(y)),
+            },
+            (Some(x), None) => match numeric(x) {
+                Some((x, true)) => compare_numbers(x, number(b)?),
+                _ => Some(x.cmp(&string(b)?[..])),
+            },
+            (None, Some(_)) => compare(b, a).map(Ordering::reverse),
+            (None, None) => compare_numbers(number(a)?, number(b)?),
+        },
+    }
+}
+
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Int(x), Number::Int(y)) => Some(x.cmp(&y)),
+        (a, b) => a.float().partial_cmp(&b.float()),
+    }
+}
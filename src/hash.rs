@@ -0,0 +1,359 @@
+use crate::kvp::{Key, KeyValuePair, NextIndex, Value};
+use crate::path::position;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+/// Path expressions over sets, after CakePHP's `Hash` class.
+///
+/// Paths are dot separated tokens. Besides plain keys, a token may be `{n}`
+/// for any numeric key, `{s}` for any string key or `{*}` for any key, and
+/// may be followed by conditions on the matched sets:
+///
+/// - `[id=1]`, `[id!=1]`, `[price>10]`, `[price<=10]` compare an element,
+///   numerically if both sides are numeric.
+/// - `[name=/^a/i]` matches an element against a regular expression.
+/// - `[text]` requires an element that is not null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Hash;
+
+impl Hash {
+    /// Extracts the values matching a path, like `Hash::extract()`.
+    ///
+    /// Unlike in PHP, a path without expressions yields the value it leads to
+    /// rather than the elements of that value.
+    pub fn extract<'a>(data: &'a Value, path: &str) -> Vec<&'a Value> {
+        if path.is_empty() {
+            return vec![data];
+        }
+        if is_simple(path) {
+            return data.get_path(path).into_iter().collect();
+        }
+        let mut context = vec![data];
+        for token in tokens(path) {
+            let selector = Selector::parse(token);
+            let mut next = Vec::new();
+            for value in context {
+                if let Value::Set(items) = value {
+                    for kvp in items {
+                        if selector.matches(kvp) {
+                            next.push(&kvp.value);
+                        }
+                    }
+                }
+            }
+            context = next;
+        }
+        context
+    }
+
+    /// Reads the first value matching a path.
+    pub fn get<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+        Self::extract(data, path).into_iter().next()
+    }
+
+    /// Inserts a value at every match of a path, like `Hash::insert()`.
+    ///
+    /// Plain paths create missing sets like [`Value::set_path`]. When the
+    /// last token is an expression, the value is merged into the matched
+    /// sets like `array_merge()` does.
+    pub fn insert(data: &mut Value, path: &str, value: Value) {
+        if is_simple(path) {
+            data.set_path(path, value);
+            return;
+        }
+        let tokens = tokens(path);
+        let selector = Selector::parse(tokens[0]);
+        let rest = tokens[1..].join(".");
+        if let Value::Set(items) = data {
+            for kvp in items {
+                if !selector.matches(kvp) {
+                    continue;
+                }
+                if rest.is_empty() {
+                    merge(&mut kvp.value, value.clone());
+                } else {
+                    Self::insert(&mut kvp.value, &rest, value.clone());
+                }
+            }
+        }
+    }
+
+    /// Removes every match of a path, like `Hash::remove()`.
+    ///
+    /// Sets left empty by removing an expression's matches are removed too.
+    pub fn remove(data: &mut Value, path: &str) {
+        if is_simple(path) {
+            data.delete_path(path);
+            return;
+        }
+        let tokens = tokens(path);
+        let selector = Selector::parse(tokens[0]);
+        let rest = tokens[1..].join(".");
+        let items = match data {
+            Value::Set(items) => items,
+            _ => return,
+        };
+        for mut kvp in std::mem::take(items) {
+            if selector.matches(&kvp) {
+                if rest.is_empty() {
+                    continue;
+                }
+                Self::remove(&mut kvp.value, &rest);
+                if matches!(&kvp.value, Value::Set(x) if x.is_empty()) {
+                    continue;
+                }
+            }
+            items.push(kvp);
+        }
+    }
+}
+
+impl Value {
+    /// Extracts the values matching a [`Hash`] path.
+    pub fn extract(&self, path: &str) -> Vec<&Value> {
+        Hash::extract(self, path)
+    }
+}
+
+fn is_simple(path: &str) -> bool {
+    !path.contains('{') && !path.contains('[')
+}
+
+/// Splits a path at the dots outside of conditions.
+fn tokens(path: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in path.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            '.' if depth == 0 => {
+                tokens.push(&path[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    tokens.push(&path[start..]);
+    tokens
+}
+
+/// Merges a set into another like `array_merge()`, renumbering integer keys.
+fn merge(target: &mut Value, value: Value) {
+    let (items, new) = match (target, value) {
+        (Value::Set(items), Value::Set(new)) => (items, new),
+        (target, value) => {
+            *target = value;
+            return;
+        }
+    };
+    let mut next = NextIndex::default();
+    for kvp in items.iter_mut() {
+        if let Key::Int(_) = kvp.key {
+            kvp.key = next.take();
+        }
+    }
+    for kvp in new {
+        match kvp.key {
+            Key::Str(_) => match position(items, &kvp.key) {
+                Some(i) => items[i].value = kvp.value,
+                None => items.push(kvp),
+            },
+            Key::Int(_) => items.push(KeyValuePair::new(next.take(), kvp.value)),
+        }
+    }
+}
+
+/// Checks whether a string is numeric, like `is_numeric()`.
+fn is_numeric(s: &str) -> bool {
+    let s = s.trim_start();
+    s.bytes().any(|b| b.is_ascii_digit())
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+        && s.parse::<f64>().is_ok()
+}
+
+/// Compares strings the way PHP's loose comparison does.
+fn loose_cmp(a: &str, b: &str) -> Ordering {
+    if is_numeric(a) && is_numeric(b) {
+        let (x, y) = (a.trim().parse::<f64>(), b.trim().parse::<f64>());
+        if let (Ok(x), Ok(y)) = (x, y) {
+            return x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+        }
+    }
+    a.cmp(b)
+}
+
+/// A path token with its conditions.
+#[derive(Debug)]
+struct Selector<'a> {
+    token: &'a str,
+    conditions: Vec<Condition>,
+}
+
+impl<'a> Selector<'a> {
+    fn parse(token: &'a str) -> Self {
+        let (token, mut rest) = match token.find('[') {
+            Some(i) => token.split_at(i),
+            None => (token, ""),
+        };
+        let mut conditions = Vec::new();
+        while let Some(body) = rest.strip_prefix('[') {
+            let mut depth = 1usize;
+            let end = body.char_indices().find_map(|(i, c)| {
+                match c {
+                    '[' => depth += 1,
+                    ']' => depth -= 1,
+                    _ => (),
+                }
+                if depth == 0 {
+                    Some(i)
+                } else {
+                    None
+                }
+            });
+            let end = end.unwrap_or(body.len());
+            conditions.push(Condition::parse(&body[..end]));
+            rest = body.get(end + 1..).unwrap_or("");
+        }
+        Self { token, conditions }
+    }
+
+    fn matches(&self, kvp: &KeyValuePair) -> bool {
+        let key = match &kvp.key {
+            Key::Int(x) => Cow::Owned(x.to_string()),
+            Key::Str(x) => Cow::Borrowed(x.as_str()),
+        };
+        let key_matches = match self.token {
+            "{n}" => is_numeric(&key),
+            "{s}" => matches!(kvp.key, Key::Str(_)),
+            "{*}" => true,
+            token if is_numeric(token) && is_numeric(&key) => {
+                loose_cmp(&key, token) == Ordering::Equal
+            }
+            token => kvp.key == Key::from(token),
+        };
+        let value = &kvp.value;
+        key_matches
+            && (self.conditions.is_empty()
+                || matches!(value, Value::Set(_))
+                    && self.conditions.iter().all(|x| x.matches(value)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+}
+
+/// A condition on an element of a matched set.
+#[derive(Debug)]
+struct Condition {
+    attr: String,
+    test: Test,
+}
+
+#[derive(Debug)]
+enum Test {
+    /// The element is not null.
+    Exists,
+
+    /// The element compares to a value.
+    Compare(Op, String),
+
+    /// The element matches a pattern. Invalid patterns never match, like a
+    /// failing `preg_match()`.
+    Pattern(Option<Regex>),
+}
+
+impl Condition {
+    fn parse(body: &str) -> Self {
+        let op = body.find(|c| "=<>!".contains(c)).and_then(|i| {
+            let (op, len) = match &body.as_bytes()[i..] {
+                [b'!', b'=', ..] => (Op::Ne, 2),
+                [b'>', b'=', ..] => (Op::Ge, 2),
+                [b'<', b'=', ..] => (Op::Le, 2),
+                [b'=', ..] => (Op::Eq, 1),
+                [b'>', ..] => (Op::Gt, 1),
+                [b'<', ..] => (Op::Lt, 1),
+                _ => return None,
+            };
+            Some((i, op, body[i + len..].trim()))
+        });
+        let (attr, test) = match op {
+            None => (body, Test::Exists),
+            Some((i, Op::Eq, value)) if value.starts_with('/') => {
+                (&body[..i], Test::Pattern(pattern(value)))
+            }
+            Some((i, op, value)) => (&body[..i], Test::Compare(op, value.to_string())),
+        };
+        Self {
+            attr: attr.trim().to_string(),
+            test,
+        }
+    }
+
+    fn matches(&self, data: &Value) -> bool {
+        let prop = match data {
+            Value::Set(items) => {
+                position(items, &Key::from(self.attr.as_str())).map(|i| &items[i].value)
+            }
+            _ => None,
+        };
+        let prop = match (&self.test, prop) {
+            (Test::Exists, prop) => return !matches!(prop, None | Some(Value::Null)),
+            (_, None) => return false,
+            (_, Some(prop)) => prop,
+        };
+        let numeric = match &self.test {
+            Test::Compare(_, value) => is_numeric(value),
+            _ => false,
+        };
+        let prop = match prop {
+            Value::Null => String::new(),
+            Value::Bool(x) if numeric => (if *x { "1" } else { "0" }).to_string(),
+            Value::Bool(x) => x.to_string(),
+            Value::Int(x) => x.to_string(),
+            Value::Float(x) => x.to_string(),
+            Value::Str(x) => x.clone(),
+            _ => return false,
+        };
+        match &self.test {
+            Test::Exists => unreachable!(),
+            Test::Pattern(pattern) => pattern.as_ref().is_some_and(|x| x.is_match(&prop)),
+            Test::Compare(op, value) => {
+                let ord = loose_cmp(&prop, value);
+                match op {
+                    Op::Eq => ord == Ordering::Equal,
+                    Op::Ne => ord != Ordering::Equal,
+                    Op::Gt => ord == Ordering::Greater,
+                    Op::Lt => ord == Ordering::Less,
+                    Op::Ge => ord != Ordering::Less,
+                    Op::Le => ord != Ordering::Greater,
+                }
+            }
+        }
+    }
+}
+
+/// Compiles a `/pattern/flags` regular expression.
+fn pattern(value: &str) -> Option<Regex> {
+    let end = value.rfind('/').filter(|&i| i > 0).unwrap_or(value.len());
+    let flags: String = value[end..]
+        .chars()
+        .filter(|c| "imsxU".contains(*c))
+        .collect();
+    let body = &value[1..end];
+    if flags.is_empty() {
+        Regex::new(body).ok()
+    } else {
+        Regex::new(&format!("(?{}){}", flags, body)).ok()
+    }
+}
@@ -1,3 +1,6 @@
+use crate::env::Env;
+use crate::lex::Span;
+use std::cmp::Ordering;
 use std::fmt::{Display, Error as FmtError, Formatter};
 
 /// A value of a node.
@@ -18,8 +21,21 @@ pub enum Value {
     /// String.
     Str(String),
 
+    /// String of bytes that are not valid UTF-8.
+    Bytes(Vec<u8>),
+
     /// Set of nodes.
     Set(Vec<KeyValuePair>),
+
+    /// Unevaluated `env()` call.
+    Env(Env),
+
+    /// Expression kept as source text.
+    Expr(Expr),
+
+    /// Source text that could not be parsed, left in place by
+    /// [`deser_str_recover`](crate::deser_str_recover).
+    Error(Expr),
 }
 
 impl Display for Value {
@@ -30,6 +46,11 @@ impl Display for Value {
             V::Bool(true) => write!(f, "true"),
             V::Bool(false) => write!(f, "false"),
             V::Int(x) => write!(f, "{}", x),
+            V::Float(x) if x.is_nan() => write!(f, "NAN"),
+            V::Float(x) if x.is_infinite() => match *x > 0.0 {
+                true => write!(f, "INF"),
+                false => write!(f, "-INF"),
+            },
             V::Float(x) => {
                 let s = format!("{}", x);
                 if s.contains('.') {
@@ -39,15 +60,84 @@ impl Display for Value {
                 }
             }
             V::Str(x) => write!(f, "{}", php_str(x)),
+            V::Bytes(x) => write!(f, "{}", php_bytes(x)),
             V::Set(x) => {
                 write!(f, "[")?;
+                let mut next = NextIndex::default();
                 for kvp in x {
-                    write!(f, "{},", kvp)?;
+                    if next.implicit(&kvp.key) {
+                        write!(f, "{},", kvp.value)?;
+                    } else {
+                        write!(f, "{},", kvp)?;
+                    }
                 }
                 write!(f, "]")
             }
+            V::Env(x) => write!(f, "{}", x),
+            V::Expr(x) | V::Error(x) => write!(f, "{}", x.text),
+        }
+    }
+}
+
+/// A PHP expression that is not evaluated, kept as its exact source text.
+///
+/// Expressions compare by their text alone, so that trees read from
+/// differently formatted files are equal.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    /// Source text of the expression.
+    pub text: String,
+
+    /// Where the expression was read from.
+    pub span: Span,
+}
+
+impl Expr {
+    /// Creates a new expression.
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl PartialOrd for Expr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.text.partial_cmp(&other.text)
+    }
+}
+
+/// Writes bytes as a double quoted literal, escaping those that are not
+/// valid UTF-8.
+pub(crate) fn php_bytes(input: &[u8]) -> String {
+    let mut d = String::with_capacity(input.len() + 2);
+    d.push('"');
+    for chunk in input.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\0' => d.push_str("\\000"),
+                '\u{0007}' => d.push_str("\\x07"),
+                '\u{001b}' => d.push_str("\\e"),
+                '\u{000c}' => d.push_str("\\f"),
+                '\r' => d.push_str("\\r"),
+                '\n' => d.push_str("\\n"),
+                '\t' => d.push_str("\\t"),
+                '\\' => d.push_str("\\\\"),
+                '"' => d.push_str("\\\""),
+                '$' => d.push_str("\\$"),
+                _ => d.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            d.push_str(&format!("\\x{:02X}", b));
         }
     }
+    d.push('"');
+    d
 }
 
 enum PHPStringState {
@@ -56,7 +146,7 @@ enum PHPStringState {
     Decided,
 }
 
-fn php_str(input: &str) -> String {
+pub(crate) fn php_str(input: &str) -> String {
     use PHPStringState as P;
     let mut d = String::new();
     let mut s = String::new();
@@ -74,7 +164,7 @@ fn php_str(input: &str) -> String {
                 }
                 '\u{0007}' => {
                     state = P::Decided;
-                    d.push_str("\\a");
+                    d.push_str("\\x07");
                 }
                 '\u{001b}' => {
                     state = P::Decided;
@@ -124,7 +214,7 @@ fn php_str(input: &str) -> String {
                 }
                 '\u{0007}' => {
                     state = P::Decided;
-                    d.push_str("\\a");
+                    d.push_str("\\x07");
                 }
                 '\u{001b}' => {
                     state = P::Decided;
@@ -175,7 +265,7 @@ fn php_str(input: &str) -> String {
             P::Decided => match c {
                 '\0' => d.push_str("\\000"),
 
-                '\u{0007}' => d.push_str("\\a"),
+                '\u{0007}' => d.push_str("\\x07"),
                 '\u{001b}' => d.push_str("\\e"),
                 '\u{000c}' => d.push_str("\\f"),
                 '\r' => d.push_str("\\r"),
@@ -207,17 +297,123 @@ fn php_str(input: &str) -> String {
 }
 
 impl Eq for Value {}
+#[allow(clippy::derive_ord_xor_partial_ord)]
 impl Ord for Value {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> Ordering {
         self.partial_cmp(other).unwrap()
     }
 }
 
+/// A set element key, normalized the way PHP normalizes array keys.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    /// Integral key.
+    Int(i64),
+
+    /// String key.
+    Str(String),
+}
+
+impl Key {
+    /// Converts a value used as a key.
+    ///
+    /// Decimal integer strings become integers, booleans and floats are cast
+    /// to integers and null becomes the empty string. Other values cannot be
+    /// keys.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(Self::Str(String::new())),
+            Value::Bool(x) => Some(Self::Int(*x as i64)),
+            Value::Int(x) => Some(Self::Int(*x)),
+            Value::Float(x) => Some(Self::Int(*x as i64)),
+            Value::Str(x) => Some(Self::from(x.as_str())),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Key {
+    fn from(key: i64) -> Self {
+        Self::Int(key)
+    }
+}
+
+impl From<&str> for Key {
+    /// Converts a string key, turning decimal integers into integer keys.
+    fn from(key: &str) -> Self {
+        let digits = key.strip_prefix('-').unwrap_or(key);
+        let canonical = match digits.as_bytes() {
+            [b'0'] => key.len() == 1,
+            [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+            _ => false,
+        };
+        match key.parse() {
+            Ok(x) if canonical => Self::Int(x),
+            _ => Self::Str(key.to_string()),
+        }
+    }
+}
+
+impl From<String> for Key {
+    /// Converts a string key, turning decimal integers into integer keys.
+    fn from(key: String) -> Self {
+        match Self::from(key.as_str()) {
+            Self::Int(x) => Self::Int(x),
+            Self::Str(_) => Self::Str(key),
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::Int(x) => write!(f, "{}", x),
+            Self::Str(x) => write!(f, "{}", php_str(x)),
+        }
+    }
+}
+
+/// Tracks the index PHP gives to the next element without a key.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct NextIndex(Option<i64>);
+
+impl NextIndex {
+    /// Takes the index for an element without a key.
+    pub(crate) fn take(&mut self) -> Key {
+        let key = Key::Int(self.0.unwrap_or(0));
+        self.observe(&key);
+        key
+    }
+
+    /// Accounts for an element with a key.
+    pub(crate) fn observe(&mut self, key: &Key) {
+        if let Key::Int(x) = *key {
+            if self.0.is_none_or(|next| x >= next) {
+                self.0 = Some(x.saturating_add(1));
+            }
+        }
+    }
+
+    /// Accounts for an element, returning whether its key is the one it
+    /// would get without a key.
+    pub(crate) fn implicit(&mut self, key: &Key) -> bool {
+        let implicit = *key == Key::Int(self.0.unwrap_or(0));
+        self.observe(key);
+        implicit
+    }
+
+    /// Checks whether keys are `0, 1, ...`, the ones a list would get.
+    pub(crate) fn is_list<'a, I: IntoIterator<Item = &'a Key>>(keys: I) -> bool {
+        let mut next = Self::default();
+        keys.into_iter().all(|x| next.implicit(x))
+    }
+}
+
 /// A graph node.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct KeyValuePair {
-    /// The name of this node, if any.
-    pub key: Option<String>,
+    /// The key of this node.
+    pub key: Key,
 
     /// The value of this node.
     pub value: Value,
@@ -225,29 +421,18 @@ pub struct KeyValuePair {
 
 impl KeyValuePair {
     /// Creates a new key-value pair.
-    pub fn new(key: Option<String>, value: Value) -> Self {
+    pub fn new(key: Key, value: Value) -> Self {
         Self { key, value }
     }
 
-    /// Applies the key prefix, if any
+    /// Applies the key prefix.
     pub fn key_prefix(&self) -> String {
-        if let Some(key) = &self.key {
-            let mut buf = String::new();
-            buf.push_str(&php_str(key));
-            buf.push_str(" => ");
-            buf
-        } else {
-            String::new()
-        }
+        format!("{} => ", self.key)
     }
 }
 
 impl Display for KeyValuePair {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
-        if let Some(key) = &self.key {
-            write!(f, "{} => {}", php_str(key), self.value)
-        } else {
-            self.value.fmt(f)
-        }
+        write!(f, "{} => {}", self.key, self.value)
     }
 }
@@ -1,12 +1,13 @@
-use observitor::Observe;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::default::Default;
 use std::fmt;
 use std::fmt::Display;
 use std::result::Result;
+use std::str::Chars;
 
 /// A lexer token.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Token {
     /// A separator. (,)
     Separator,
@@ -19,6 +20,12 @@ pub enum Token {
     /// Set closing. (])
     CloseSet,
 
+    /// Opening parenthesis. (()
+    OpenParen,
+
+    /// Closing parenthesis. ())
+    CloseParen,
+
     /// Integer value.
     Int(String),
 
@@ -33,51 +40,85 @@ pub enum Token {
 
     /// Double quoted literal.
     DoubleQuote(String),
+
+    /// Double quoted literal whose escapes make it invalid UTF-8.
+    Bytes(Vec<u8>),
+
+    /// Operator. (-, +, ., ::, ...)
+    Operator(String),
+
+    /// Block opening. ({)
+    OpenBrace,
+
+    /// Block closing. (})
+    CloseBrace,
+
+    /// Variable. ($name)
+    Variable(String),
+
+    /// Statement terminator. (;)
+    Terminator,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
-pub enum State {
+/// Operators, longest match wins.
+const OPERATORS: &[&str] = &[
+    "!", "!=", "!==", "$", "%", "%=", "&", "&&", "&=", "*", "**", "**=", "*=", "+", "++", "+=",
+    "-", "--", "-=", "->", ".", ".=", "...", "/", "/=", ":", "::", "<", "<<", "<<=", "<=", "<=>",
+    "<>", "=", "==", "===", ">", ">=", ">>", ">>=", "?", "??", "??=", "?->", "@", "^", "^=", "|",
+    "|=", "||", "~",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Default)]
+pub(crate) enum State {
+    #[default]
     Initial,
-    PrepareComment,
-    PrepareAssignment,
+    Operator,
+    Variable,
     SingleQuote,
     DoubleQuote,
     Integer,
-    IntegerDecimal,
+    IntegerSeparator,
+    Zero,
+    Octal,
+    OctalPrefix,
+    OctalSeparator,
+    Hex,
+    HexPrefix,
+    HexSeparator,
+    Binary,
+    BinaryPrefix,
+    BinarySeparator,
+    DecimalPoint,
     Identifier,
-    PrepareOpenTag,
-    PrepareCloseTag,
     LineComment,
     MultiLineComment,
     MultiLineCommentPrepareExit,
     Decimal,
+    DecimalSeparator,
+    ExponentMark,
+    ExponentSign,
+    Exponent,
+    ExponentSeparator,
     SingleQuoteEscape,
     DoubleQuoteEscape,
-    DoubleQuoteEscapeControl,
-    DoubleQuoteEscapeHex,
-    DoubleQuoteEscapeOctal2,
-    DoubleQuoteEscapeHexBound,
-    DoubleQuoteEscapeHex2,
     PHPTag0,
     PHPTag1,
     PHPTag2,
-    DoubleQuoteEscapeOctal3,
-}
-
-impl Default for State {
-    fn default() -> Self {
-        Self::Initial
-    }
+    HeredocOpen,
+    HeredocLabel,
+    HeredocOpenEnd,
+    HeredocBody,
+    HeredocEnd,
 }
 
 /// A token position.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
 pub struct Position {
-    /// Absolute position of the token.
+    /// Absolute byte offset of the token.
     pub index: usize,
-    /// Line of the token.
+    /// Line of the token, counting from zero.
     pub line: usize,
-    /// Column on the line.
+    /// Character on the line, counting from zero.
     pub column: usize,
 }
 
@@ -89,7 +130,7 @@ impl Default for Position {
 
 impl Display for Position {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "Line {}, Column {}", self.line, self.column)
+        write!(f, "line {}, column {}", self.line + 1, self.column + 1)
     }
 }
 
@@ -103,10 +144,10 @@ impl Position {
         }
     }
 
-    /// Advances the position.
-    pub fn advance(&mut self, new_line: bool) {
-        self.index += 1;
-        if new_line {
+    /// Advances the position past a character.
+    pub fn advance(&mut self, c: char) {
+        self.index += c.len_utf8();
+        if c == '\n' {
             self.line += 1;
             self.column = 0;
         } else {
@@ -115,6 +156,22 @@ impl Position {
     }
 }
 
+/// Source range of a token.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Default)]
+pub struct Span {
+    /// Position of the first character.
+    pub start: Position,
+    /// Position just past the last character.
+    pub end: Position,
+}
+
+impl Span {
+    /// Creates a new span.
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+}
+
 /// Kind of lexer error.
 #[derive(Debug, Clone, PartialEq, Eq, Copy, PartialOrd, Ord)]
 pub enum LexErrorKind {
@@ -124,6 +181,8 @@ pub enum LexErrorKind {
     Unexpected(char),
     /// Invalid Unicode escape.
     InvalidUnicode(u32),
+    /// Heredoc line indented less than the closing marker.
+    InvalidIndentation,
 }
 
 /// Lexer error.
@@ -137,8 +196,7 @@ pub struct LexError {
 }
 
 impl LexError {
-    /// Creates a new lexer error.
-    pub fn new(position: Position, state: State, kind: LexErrorKind) -> Self {
+    pub(crate) fn new(position: Position, state: State, kind: LexErrorKind) -> Self {
         Self {
             position,
             state,
@@ -160,510 +218,844 @@ impl fmt::Display for LexError {
             K::Unexpected('\r') => write!(f, "Unexpected carriage return"),
             K::Unexpected('\t') => write!(f, "Unexpected tab"),
             K::Unexpected(' ') => write!(f, "Unexpected space"),
-            K::Unexpected(c) => write!(f, "'{}'", c),
-            K::EOF => write!(f, "end of file"),
-            K::InvalidUnicode(x) => write!(f, "Invalid codepoint U+{:#X}", x),
+            K::Unexpected(c) => write!(f, "Unexpected character {:?}", c),
+            K::EOF => write!(f, "Unexpected end of file"),
+            K::InvalidUnicode(x) => write!(f, "Invalid codepoint U+{:X}", x),
+            K::InvalidIndentation => write!(f, "Invalid heredoc indentation"),
         }?;
-        write!(f, "at line {}, column {} ", position.column, position.line)?;
+        write!(f, " at {} ", position)?;
         write!(f, "in state {:?}", state)
     }
 }
 
-/// Feeds characters into lexer.
-pub fn lex<I: IntoIterator<Item = char>, O: Observe<(Position, Token)>>(
-    state: &mut State,
-    position: &mut Position,
-    token_position: &mut Position,
-    buffer: &mut String,
-    codepoint: &mut u32,
-    source: I,
-    dest: &mut O,
-) -> Result<(), LexError> {
-    use LexErrorKind as K;
-    use State as S;
-    use Token as T;
-    let s = state;
-    let p = position;
-    let t = token_position;
-    let b = buffer;
-    let cp = codepoint;
-    let d = dest;
-    for c in source {
-        p.advance(c == '\n');
-        match *s {
-            S::Initial => match c {
-                ' ' | '\r' | '\n' | '\t' => (),
-                '/' => *s = S::PrepareComment,
-                '[' => d.update((*p, T::OpenSet)),
-                ']' => d.update((*p, T::CloseSet)),
-                '=' => *s = S::PrepareAssignment,
-                '\'' => {
-                    *t = *p;
-                    *s = S::SingleQuote;
+/// Incremental PHP lexer.
+///
+/// Chunks are pushed in with [`Lexer::feed`] and [`Lexer::finish`], and the
+/// tokens completed so far are drained by iterating over the lexer. For a
+/// string that is already in memory, [`Lexer::tokens`] pulls tokens lazily.
+#[derive(Debug, Clone, Default)]
+pub struct Lexer {
+    state: State,
+    position: Position,
+    start: Position,
+    buffer: String,
+    heredoc: Heredoc,
+    queue: VecDeque<(Span, Token)>,
+    error: Option<LexError>,
+    /// Whether iterating has returned the error, which ends it.
+    error_returned: bool,
+}
+
+impl Lexer {
+    /// Creates a new lexer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a pull iterator over the tokens of a string.
+    pub fn tokens(input: &str) -> Tokens<'_> {
+        Tokens {
+            lexer: Self::new(),
+            source: input.chars(),
+            done: false,
+        }
+    }
+
+    /// Position of the next character to be fed.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Feeds a chunk of characters into the lexer.
+    ///
+    /// Once an error has been returned, it is returned again for every
+    /// following chunk.
+    pub fn feed(&mut self, chunk: &str) -> Result<(), LexError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        for c in chunk.chars() {
+            if let Err(err) = self.step(c) {
+                self.error = Some(err);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Feeds end-of-file into the lexer.
+    pub fn finish(&mut self) -> Result<(), LexError> {
+        use LexErrorKind as K;
+        use State as S;
+        use Token as T;
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let b = std::mem::take(&mut self.buffer);
+        match self.state {
+            S::Initial | S::LineComment | S::MultiLineComment | S::MultiLineCommentPrepareExit => {}
+            S::SingleQuote
+            | S::DoubleQuote
+            | S::SingleQuoteEscape
+            | S::DoubleQuoteEscape
+            | S::PHPTag0
+            | S::PHPTag1
+            | S::PHPTag2
+            | S::HeredocOpen
+            | S::HeredocLabel
+            | S::HeredocOpenEnd
+            | S::HeredocBody
+            | S::IntegerSeparator
+            | S::OctalPrefix
+            | S::OctalSeparator
+            | S::HexPrefix
+            | S::HexSeparator
+            | S::BinaryPrefix
+            | S::BinarySeparator
+            | S::DecimalSeparator
+            | S::ExponentMark
+            | S::ExponentSign
+            | S::ExponentSeparator => {
+                let err = LexError::new(self.position, self.state, K::EOF);
+                self.error = Some(err);
+                return Err(err);
+            }
+            S::Integer | S::Zero | S::Octal | S::Hex | S::Binary => {
+                if let Some(err) = self.invalid_octal(&b) {
+                    self.error = Some(err);
+                    return Err(err);
                 }
-                '"' => {
-                    *t = *p;
-                    *s = S::DoubleQuote;
+                self.emit(T::Int(b))
+            }
+            S::Identifier => self.emit(T::Identifier(b)),
+            S::Operator => {
+                self.buffer = b;
+                self.end_operator();
+            }
+            S::Variable if b.is_empty() => self.emit(T::Operator("$".into())),
+            S::Variable => self.emit(T::Variable(b)),
+            S::DecimalPoint | S::Decimal | S::Exponent => self.emit(T::Float(b)),
+            S::HeredocEnd => {
+                self.buffer = b;
+                match self.heredoc_token() {
+                    Ok(token) => self.emit(token),
+                    Err(err) => {
+                        self.error = Some(err);
+                        return Err(err);
+                    }
                 }
-                '0'..='9' => {
-                    *t = *p;
-                    b.clear();
+            }
+        }
+        self.state = S::Initial;
+        Ok(())
+    }
+
+    fn step(&mut self, c: char) -> Result<(), LexError> {
+        if c == '\u{FEFF}' && self.position.index == 0 {
+            // byte order mark, invisible in editors
+            self.position.index += c.len_utf8();
+            return Ok(());
+        }
+        while !self.consume(c)? {}
+        self.position.advance(c);
+        Ok(())
+    }
+
+    /// Queues a token that started at `self.start` and ends here.
+    fn emit(&mut self, token: Token) {
+        self.queue
+            .push_back((Span::new(self.start, self.position), token));
+    }
+
+    /// Queues a token made of the current character alone.
+    fn emit_char(&mut self, c: char, token: Token) {
+        let mut end = self.position;
+        end.advance(c);
+        self.queue.push_back((Span::new(self.position, end), token));
+    }
+
+    /// Starts a new token at the current character.
+    fn begin(&mut self, state: State) {
+        self.start = self.position;
+        self.buffer.clear();
+        self.state = state;
+    }
+
+    /// Feeds a single character in the current state.
+    ///
+    /// Returns `false` when the character ended a token without being part of
+    /// it, and has to be fed again in the new state.
+    fn consume(&mut self, c: char) -> Result<bool, LexError> {
+        use LexErrorKind as K;
+        use State as S;
+        use Token as T;
+        let p = self.position;
+        let b = &mut self.buffer;
+        match self.state {
+            S::Initial => match c {
+                ' ' | '\r' | '\n' | '\t' => (),
+                '#' => self.state = S::LineComment,
+                ';' => self.emit_char(c, T::Terminator),
+                '[' => self.emit_char(c, T::OpenSet),
+                ']' => self.emit_char(c, T::CloseSet),
+                '(' => self.emit_char(c, T::OpenParen),
+                ')' => self.emit_char(c, T::CloseParen),
+                '{' => self.emit_char(c, T::OpenBrace),
+                '}' => self.emit_char(c, T::CloseBrace),
+                '\'' => self.begin(S::SingleQuote),
+                '"' => self.begin(S::DoubleQuote),
+                '0' => {
+                    self.begin(S::Zero);
+                    self.buffer.push(c);
+                }
+                '1'..='9' => {
+                    self.begin(S::Integer);
+                    self.buffer.push(c);
+                }
+                '$' => self.begin(S::Variable),
+                'A'..='Z' | 'a'..='z' | '_' | '\\' | '\u{80}'..=std::char::MAX => {
+                    self.begin(S::Identifier);
+                    self.buffer.push(c);
+                }
+                ',' => self.emit_char(c, T::Separator),
+                _ if is_operator_prefix(&c.to_string()) => {
+                    self.begin(S::Operator);
+                    self.buffer.push(c);
+                }
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::Operator => match (b.as_str(), c) {
+                ("/", '/') => self.state = S::LineComment,
+                ("/", '*') => self.state = S::MultiLineComment,
+                ("<", '?') => self.state = S::PHPTag0,
+                ("?", '>') => self.state = S::Initial,
+                ("=", '>') => self.emit_through(c, T::Assignment),
+                ("<<", '<') => {
+                    self.heredoc = Heredoc::default();
+                    self.state = S::HeredocOpen;
+                }
+                (".", '0'..='9') => {
                     b.push(c);
-                    *s = S::Integer;
+                    self.state = S::Decimal;
                 }
-                'A'..='Z' | 'a'..='z' | '_' => {
-                    *t = *p;
-                    b.clear();
+                _ => {
                     b.push(c);
-                    *s = S::Identifier;
+                    if !is_operator_prefix(b) {
+                        b.pop();
+                        self.end_operator();
+                        return Ok(false);
+                    }
                 }
-                ',' => d.update((*p, T::Separator)),
-                '<' => *s = S::PrepareOpenTag,
-                '?' => *s = S::PrepareCloseTag,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
-            },
-            S::PrepareComment => match c {
-                '/' => *s = S::LineComment,
-                '*' => *s = S::MultiLineComment,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
             },
-            S::PrepareAssignment => match c {
-                '>' => {
-                    d.update((*p, T::Assignment));
-                    *s = S::Initial;
+            S::Variable => match c {
+                '0'..='9' | '_' | 'a'..='z' | 'A'..='Z' | '\u{80}'..=std::char::MAX => b.push(c),
+                _ => {
+                    let token = if b.is_empty() {
+                        T::Operator("$".into())
+                    } else {
+                        T::Variable(b.clone())
+                    };
+                    self.emit(token);
+                    self.state = S::Initial;
+                    return Ok(false);
                 }
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
             },
             S::SingleQuote => match c {
                 '\'' => {
-                    d.update((*t, T::SingleQuote(b.clone())));
-                    *s = S::Initial;
+                    let token = T::SingleQuote(b.clone());
+                    self.emit_through(c, token);
                 }
-                '\\' => *s = S::SingleQuoteEscape,
+                '\\' => self.state = S::SingleQuoteEscape,
                 _ => b.push(c),
             },
             S::DoubleQuote => match c {
                 '"' => {
-                    d.update((*t, T::DoubleQuote(b.clone())));
-                    *s = S::Initial;
+                    let token = match unescape(b, false, c) {
+                        Ok(x) => bytes_token(x),
+                        Err((offset, kind)) => {
+                            let mut start = self.start;
+                            start.advance('"');
+                            let position = walk(start, &b[..offset]);
+                            return Err(LexError::new(position, self.state, kind));
+                        }
+                    };
+                    self.emit_through(c, token);
+                }
+                '\\' => {
+                    b.push(c);
+                    self.state = S::DoubleQuoteEscape;
                 }
-                '\\' => *s = S::DoubleQuoteEscape,
                 _ => b.push(c),
             },
             S::Integer => match c {
-                ' ' | '\r' | '\n' | '\t' | ';' => {
-                    d.update((*t, T::Int(b.clone())));
-                    *s = S::Initial;
-                }
-                '/' => {
-                    d.update((*t, T::Int(b.clone())));
-                    *s = S::PrepareComment;
-                }
-                '[' => {
-                    d.update((*t, T::Int(b.clone())));
-                    d.update((*p, T::OpenSet));
-                    *s = S::Initial;
+                '0'..='9' => b.push(c),
+                '_' => {
+                    b.push(c);
+                    self.state = S::IntegerSeparator;
                 }
-                ']' => {
-                    d.update((*t, T::Int(b.clone())));
-                    d.update((*p, T::CloseSet));
-                    *s = S::Initial;
+                '.' => {
+                    b.push(c);
+                    self.state = S::DecimalPoint;
                 }
-                '=' => {
-                    d.update((*t, T::Int(b.clone())));
-                    *s = S::PrepareAssignment;
+                'E' | 'e' => {
+                    b.push(c);
+                    self.state = S::ExponentMark;
                 }
-                '\'' => {
-                    d.update((*t, T::Int(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::SingleQuote;
+                _ => return self.end_number(c, false),
+            },
+            S::IntegerSeparator => match c {
+                '0'..='9' => {
+                    b.push(c);
+                    self.state = S::Integer;
                 }
-                ',' => {
-                    d.update((*t, T::Int(b.clone())));
-                    d.update((*p, T::Separator));
-                    *s = S::Initial;
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::Zero => match c {
+                'X' | 'x' => {
+                    b.push(c);
+                    self.state = S::HexPrefix;
                 }
-                '"' => {
-                    d.update((*t, T::Int(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::DoubleQuote;
+                'O' | 'o' => {
+                    b.push(c);
+                    self.state = S::OctalPrefix;
                 }
-                '0'..='0' | 'E' | 'e' | '+' => b.push(c),
-                '-' | '.' => {
+                'B' | 'b' => {
                     b.push(c);
-                    *s = S::Decimal;
+                    self.state = S::BinaryPrefix;
                 }
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
-            },
-            S::IntegerDecimal => match c {
                 '0'..='9' => {
                     b.push(c);
-                    *s = S::Integer;
+                    self.state = S::Integer;
+                }
+                '_' => {
+                    b.push(c);
+                    self.state = S::IntegerSeparator;
                 }
                 '.' => {
-                    b.push('.');
-                    *s = S::Decimal;
+                    b.push(c);
+                    self.state = S::DecimalPoint;
                 }
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                'E' | 'e' => {
+                    b.push(c);
+                    self.state = S::ExponentMark;
+                }
+                _ => return self.end_number(c, false),
             },
-            S::Identifier => match c {
-                ' ' | '\r' | '\n' | '\t' | ';' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    *s = S::Initial;
+            S::Octal => match c {
+                '0'..='7' => b.push(c),
+                '_' => {
+                    b.push(c);
+                    self.state = S::OctalSeparator;
                 }
-                '/' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    *s = S::PrepareComment;
+                _ => return self.end_number(c, false),
+            },
+            S::OctalPrefix | S::OctalSeparator => match c {
+                '0'..='7' => {
+                    b.push(c);
+                    self.state = S::Octal;
                 }
-                '[' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    d.update((*p, T::OpenSet));
-                    *s = S::Initial;
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::Hex => match c {
+                '0'..='9' | 'A'..='F' | 'a'..='f' => b.push(c),
+                '_' => {
+                    b.push(c);
+                    self.state = S::HexSeparator;
                 }
-                ']' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    d.update((*p, T::CloseSet));
-                    *s = S::Initial;
+                _ => return self.end_number(c, false),
+            },
+            S::HexPrefix | S::HexSeparator => match c {
+                '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                    b.push(c);
+                    self.state = S::Hex;
                 }
-                '=' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    *s = S::PrepareAssignment;
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::Binary => match c {
+                '0' | '1' => b.push(c),
+                '_' => {
+                    b.push(c);
+                    self.state = S::BinarySeparator;
                 }
-                '\'' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::SingleQuote;
+                _ => return self.end_number(c, false),
+            },
+            S::BinaryPrefix | S::BinarySeparator => match c {
+                '0' | '1' => {
+                    b.push(c);
+                    self.state = S::Binary;
                 }
-                '"' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::DoubleQuote;
-                }
-                ',' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    d.update((*p, T::Separator));
-                    *s = S::Initial;
-                }
-                '<' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    *s = S::PrepareOpenTag;
-                }
-                '?' => {
-                    d.update((*t, T::Identifier(b.clone())));
-                    *s = S::PrepareCloseTag;
-                }
-                '0'..='9' | '_' | 'a'..='z' | 'A'..='Z' => b.push(c),
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
-            S::PrepareOpenTag => match c {
-                '?' => *s = S::PHPTag0,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+            S::DecimalPoint => match c {
+                '0'..='9' => {
+                    b.push(c);
+                    self.state = S::Decimal;
+                }
+                'E' | 'e' => {
+                    b.push(c);
+                    self.state = S::ExponentMark;
+                }
+                _ => return self.end_number(c, true),
             },
-            S::PrepareCloseTag => match c {
-                '>' => *s = S::Initial,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+            S::Identifier => match c {
+                '0'..='9' | '_' | 'a'..='z' | 'A'..='Z' | '\\' | '\u{80}'..=std::char::MAX => {
+                    b.push(c)
+                }
+                _ => {
+                    let token = T::Identifier(b.clone());
+                    self.emit(token);
+                    self.state = S::Initial;
+                    return Ok(false);
+                }
             },
             S::LineComment => {
                 if c == '\n' {
-                    *s = S::Initial
+                    self.state = S::Initial
                 }
             }
             S::MultiLineComment => {
                 if c == '*' {
-                    *s = S::MultiLineCommentPrepareExit
+                    self.state = S::MultiLineCommentPrepareExit
                 }
             }
             S::MultiLineCommentPrepareExit => match c {
                 '*' => (),
-                '/' => *s = S::Initial,
-                _ => *s = S::MultiLineComment,
+                '/' => self.state = S::Initial,
+                _ => self.state = S::MultiLineComment,
             },
             S::Decimal => match c {
-                ' ' | '\r' | '\n' | '\t' | ';' => {
-                    d.update((*t, T::Float(b.clone())));
-                    *s = S::Initial;
-                }
-                '/' => {
-                    d.update((*t, T::Float(b.clone())));
-                    *s = S::PrepareComment;
-                }
-                '[' => {
-                    d.update((*t, T::Float(b.clone())));
-                    d.update((*p, T::OpenSet));
-                    *s = S::Initial;
-                }
-                ']' => {
-                    d.update((*t, T::Float(b.clone())));
-                    d.update((*p, T::CloseSet));
-                    *s = S::Initial;
-                }
-                '=' => {
-                    d.update((*t, T::Float(b.clone())));
-                    *s = S::PrepareAssignment;
-                }
-                '\'' => {
-                    d.update((*t, T::Float(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::SingleQuote;
-                }
-                ',' => {
-                    d.update((*t, T::Float(b.clone())));
-                    d.update((*p, T::Separator));
-                    *s = S::Initial;
-                }
-                '"' => {
-                    d.update((*t, T::Float(b.clone())));
-                    b.clear();
-                    *t = *p;
-                    *s = S::DoubleQuote;
-                }
-                '0'..='0' | 'E' | 'e' | '+' | '-' | '.' => b.push(c),
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
-            },
-            S::SingleQuoteEscape => match c {
-                '\\' | '\'' => {
+                '0'..='9' => b.push(c),
+                '_' => {
                     b.push(c);
-                    *s = S::SingleQuote;
+                    self.state = S::DecimalSeparator;
                 }
-                _ => {
-                    b.push('\\');
+                'E' | 'e' => {
                     b.push(c);
-                    *s = S::SingleQuote;
+                    self.state = S::ExponentMark;
                 }
+                _ => return self.end_number(c, true),
             },
-            S::DoubleQuoteEscape => match c {
-                'a' => {
-                    b.push('\u{0007}');
-                    *s = S::DoubleQuote;
-                }
-                'c' => *s = S::DoubleQuoteEscapeControl,
-                'e' => {
-                    b.push('\u{001b}');
-                    *s = S::DoubleQuote;
-                }
-                'f' => {
-                    b.push('\u{000c}');
-                    *s = S::DoubleQuote;
-                }
-                'n' => {
-                    b.push('\n');
-                    *s = S::DoubleQuote;
-                }
-                'r' => {
-                    b.push('\r');
-                    *s = S::DoubleQuote;
-                }
-                't' => {
-                    b.push('\t');
-                    *s = S::DoubleQuote;
-                }
-                '$' => {
-                    b.push('$');
-                    *s = S::DoubleQuote;
-                }
-                '"' => {
-                    b.push('"');
-                    *s = S::DoubleQuote;
-                }
-                '\\' => {
-                    b.push('\\');
-                    *s = S::DoubleQuote;
-                }
-                'x' => {
-                    *cp = 0;
-                    *s = S::DoubleQuoteEscapeHex;
-                }
+            S::DecimalSeparator => match c {
                 '0'..='9' => {
-                    *cp = (c as u32) - ('0' as u32);
-                    *s = S::DoubleQuoteEscapeOctal2;
+                    b.push(c);
+                    self.state = S::Decimal;
                 }
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
-            S::DoubleQuoteEscapeControl => {
-                match char::try_from((c.to_uppercase().next().unwrap() as u32) ^ 0x60) {
-                    Ok(x) => {
-                        b.push(x);
-                        *s = S::DoubleQuote;
-                    }
-                    Err(_) => {
-                        return Err(LexError::new(
-                            *p,
-                            *s,
-                            K::InvalidUnicode((c.to_uppercase().next().unwrap() as u32) ^ 0x60),
-                        ))
-                    }
+            S::ExponentMark => match c {
+                '+' | '-' => {
+                    b.push(c);
+                    self.state = S::ExponentSign;
                 }
-            }
-            S::DoubleQuoteEscapeHex => match c {
-                '{' => *s = S::DoubleQuoteEscapeHexBound,
                 '0'..='9' => {
-                    *cp = *cp | ((c as u32) - '0' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                'a'..='f' => {
-                    *cp = *cp | ((c as u32) - 'a' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                'A'..='F' => {
-                    *cp = *cp | ((c as u32) - 'A' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
-            },
-            S::DoubleQuoteEscapeOctal2 => match c {
-                '0'..='7' => {
-                    *cp = (*cp << 3) | ((c as u32) - ('0' as u32));
-                    *s = S::DoubleQuoteEscapeOctal3;
-                }
-                '\\' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuoteEscape;
-                }
-                '"' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    d.update((*t, T::DoubleQuote(b.clone())));
-                    *s = S::Initial;
-                }
-                _ => {
-                    b.push(u8::try_from(*cp).unwrap().into());
                     b.push(c);
-                    *s = S::DoubleQuote;
+                    self.state = S::Exponent;
                 }
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
-            S::DoubleQuoteEscapeHexBound => match c {
-                '0'..='9' => {
-                    *cp = (*cp << 4) | ((c as u32) - '0' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                'a'..='f' => {
-                    *cp = (*cp << 4) | ((c as u32) - 'a' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                'A'..='F' => {
-                    *cp = (*cp << 4) | ((c as u32) - 'A' as u32);
-                    *s = S::DoubleQuoteEscapeHex2;
-                }
-                '}' => match char::try_from(*cp) {
-                    Ok(x) => b.push(x),
-                    Err(_) => return Err(LexError::new(*p, *s, K::InvalidUnicode(*cp))),
-                },
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
-            },
-            S::DoubleQuoteEscapeHex2 => match c {
+            S::ExponentSign | S::ExponentSeparator => match c {
                 '0'..='9' => {
-                    *cp = (*cp << 4) | ((c as u32) - '0' as u32);
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuote;
-                }
-                'a'..='f' => {
-                    *cp = (*cp << 4) | ((c as u32) - 'a' as u32);
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuote;
-                }
-                'A'..='F' => {
-                    *cp = (*cp << 4) | ((c as u32) - 'A' as u32);
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuote;
+                    b.push(c);
+                    self.state = S::Exponent;
                 }
-                '\\' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuoteEscape;
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::Exponent => match c {
+                '0'..='9' => b.push(c),
+                '_' => {
+                    b.push(c);
+                    self.state = S::ExponentSeparator;
                 }
-                '"' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    d.update((*t, T::DoubleQuote(b.clone())));
-                    *s = S::Initial;
+                _ => return self.end_number(c, true),
+            },
+            S::SingleQuoteEscape => match c {
+                '\\' | '\'' => {
+                    b.push(c);
+                    self.state = S::SingleQuote;
                 }
                 _ => {
-                    b.push(u8::try_from(*cp).unwrap().into());
+                    b.push('\\');
                     b.push(c);
-                    *s = S::DoubleQuote;
+                    self.state = S::SingleQuote;
                 }
             },
+            S::DoubleQuoteEscape => {
+                b.push(c);
+                self.state = S::DoubleQuote;
+            }
             S::PHPTag0 => match c {
-                '=' => *s = S::Initial,
-                'p' => *s = S::PHPTag1,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                '=' => self.state = S::Initial,
+                'p' => self.state = S::PHPTag1,
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
             S::PHPTag1 => match c {
-                'h' => *s = S::PHPTag2,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                'h' => self.state = S::PHPTag2,
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
             S::PHPTag2 => match c {
-                'p' => *s = S::Initial,
-                _ => return Err(LexError::new(*p, *s, K::Unexpected(c))),
+                'p' => self.state = S::Initial,
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
             },
-            S::DoubleQuoteEscapeOctal3 => match c {
-                '0'..='7' => {
-                    *cp = (*cp << 3) | ((c as u32) - ('0' as u32));
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuote;
+            S::HeredocOpen => match c {
+                ' ' | '\t' => (),
+                '\'' | '"' => {
+                    self.heredoc.quote = Some(c);
+                    self.state = S::HeredocLabel;
                 }
-                '\\' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    *s = S::DoubleQuoteEscape;
+                'A'..='Z' | 'a'..='z' | '_' | '\u{80}'..=std::char::MAX => {
+                    self.heredoc.label.push(c);
+                    self.state = S::HeredocLabel;
                 }
-                '"' => {
-                    b.push(u8::try_from(*cp).unwrap().into());
-                    d.update((*t, T::DoubleQuote(b.clone())));
-                    *s = S::Initial;
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::HeredocLabel => {
+                let h = &mut self.heredoc;
+                match c {
+                    _ if is_label_char(c) && (!h.label.is_empty() || !c.is_ascii_digit()) => {
+                        h.label.push(c)
+                    }
+                    _ if h.label.is_empty() => {
+                        return Err(LexError::new(p, self.state, K::Unexpected(c)))
+                    }
+                    '\r' if h.quote.is_none() => self.state = S::HeredocOpenEnd,
+                    '\n' if h.quote.is_none() => self.open_heredoc(c),
+                    _ if h.quote == Some(c) => self.state = S::HeredocOpenEnd,
+                    _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
                 }
-                _ => {
-                    b.push(u8::try_from(*cp).unwrap().into());
+            }
+            S::HeredocOpenEnd => match c {
+                '\r' => (),
+                '\n' => self.open_heredoc(c),
+                _ => return Err(LexError::new(p, self.state, K::Unexpected(c))),
+            },
+            S::HeredocBody => {
+                b.push(c);
+                let h = &mut self.heredoc;
+                if c == '\n' {
+                    h.line = b.len();
+                } else if b[h.line..].trim_start_matches(&[' ', '\t'][..]) == h.label {
+                    self.state = S::HeredocEnd;
+                }
+            }
+            S::HeredocEnd => {
+                if is_label_char(c) {
                     b.push(c);
-                    *s = S::DoubleQuote;
+                    self.state = S::HeredocBody;
+                } else {
+                    let token = self.heredoc_token()?;
+                    self.emit(token);
+                    self.state = S::Initial;
+                    return Ok(false);
                 }
-            },
+            }
+        }
+        Ok(true)
+    }
+
+    /// Starts the body of a heredoc after the newline ending its opening
+    /// line.
+    fn open_heredoc(&mut self, newline: char) {
+        self.buffer.clear();
+        self.heredoc.line = 0;
+        self.heredoc.body = self.position;
+        self.heredoc.body.advance(newline);
+        self.state = State::HeredocBody;
+    }
+
+    /// Completes a heredoc or nowdoc whose closing marker ends the buffer.
+    ///
+    /// The indentation of the closing marker is removed from every line of
+    /// the body, like PHP 7.3 does.
+    fn heredoc_token(&mut self) -> Result<Token, LexError> {
+        let raw = std::mem::take(&mut self.buffer);
+        let h = std::mem::take(&mut self.heredoc);
+        let indent = &raw[h.line..raw.len() - h.label.len()];
+        let body = match h.line {
+            0 => "",
+            n => raw[..n - 1].strip_suffix('\r').unwrap_or(&raw[..n - 1]),
+        };
+        let mut text = String::with_capacity(body.len());
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for (i, line) in body.split('\n').enumerate() {
+            let stripped = match line.strip_prefix(indent) {
+                Some(x) => x,
+                None => {
+                    let x = line.trim_start_matches(&[' ', '\t'][..]);
+                    if !x.is_empty() && x != "\r" {
+                        let position = walk(h.body, &raw[..offset]);
+                        let kind = LexErrorKind::InvalidIndentation;
+                        return Err(LexError::new(position, self.state, kind));
+                    }
+                    x
+                }
+            };
+            if i > 0 {
+                text.push('\n');
+            }
+            lines.push((text.len(), offset + line.len() - stripped.len()));
+            text.push_str(stripped);
+            offset += line.len() + 1;
+        }
+        if h.quote == Some('\'') {
+            return Ok(Token::SingleQuote(text));
+        }
+        match unescape(&text, true, '\n') {
+            Ok(x) => Ok(bytes_token(x)),
+            Err((offset, kind)) => {
+                let (start, line) = lines[lines.iter().rposition(|x| x.0 <= offset).unwrap_or(0)];
+                let position = walk(h.body, &raw[..line + offset - start]);
+                Err(LexError::new(position, self.state, kind))
+            }
+        }
+    }
+
+    /// Queues the buffered operator, splitting it when it is only the prefix
+    /// of a longer one, as in `?-`.
+    fn end_operator(&mut self) {
+        let op = std::mem::take(&mut self.buffer);
+        let mut len = op.len();
+        while !OPERATORS.contains(&&op[..len]) {
+            len -= 1;
+        }
+        let mut start = self.start;
+        let mut end = start;
+        op[..len].chars().for_each(|c| end.advance(c));
+        self.queue.push_back((
+            Span::new(start, end),
+            Token::Operator(op[..len].to_string()),
+        ));
+        for c in op[len..].chars() {
+            start = end;
+            end.advance(c);
+            self.queue
+                .push_back((Span::new(start, end), Token::Operator(c.to_string())));
+        }
+        self.state = State::Initial;
+    }
+
+    /// Ends a numeric literal at a character that is not part of it.
+    fn end_number(&mut self, c: char, float: bool) -> Result<bool, LexError> {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            return Err(LexError::new(
+                self.position,
+                self.state,
+                LexErrorKind::Unexpected(c),
+            ));
+        }
+        if let Some(err) = self.invalid_octal(&self.buffer).filter(|_| !float) {
+            return Err(err);
+        }
+        let b = self.buffer.clone();
+        self.emit(if float {
+            Token::Float(b)
+        } else {
+            Token::Int(b)
+        });
+        self.state = State::Initial;
+        Ok(false)
+    }
+
+    /// Digits after a leading zero are lexed as decimals, since they may
+    /// turn out to be a float like `09.5`. Those ending as an integer are
+    /// octal, and must not have an `8` or `9`.
+    fn invalid_octal(&self, b: &str) -> Option<LexError> {
+        if !b.starts_with('0') || b.starts_with("0x") || b.starts_with("0X") {
+            return None;
         }
+        let i = b.find(&['8', '9'][..])?;
+        let c = b[i..].chars().next()?;
+        let p = walk(self.start, &b[..i]);
+        Some(LexError::new(p, self.state, LexErrorKind::Unexpected(c)))
+    }
+
+    /// Queues a token that ends with the current character.
+    fn emit_through(&mut self, c: char, token: Token) {
+        let mut end = self.position;
+        end.advance(c);
+        self.queue.push_back((Span::new(self.start, end), token));
+        self.state = State::Initial;
     }
-    Ok(())
 }
 
-/// Feeds end-of-file into lexer.
-pub fn eof<O: Observe<(Position, Token)>>(
-    state: State,
-    position: Position,
-    token_position: Position,
-    buffer: String,
-    dest: &mut O,
-) -> Result<(), LexError> {
-    use LexError as E;
+/// State of a heredoc being lexed.
+#[derive(Debug, Clone, Default)]
+struct Heredoc {
+    /// Label of the closing marker.
+    label: String,
+    /// Quote around the opening label; a single quote makes a nowdoc.
+    quote: Option<char>,
+    /// Start of the current line in the buffer.
+    line: usize,
+    /// Position of the first character of the body.
+    body: Position,
+}
+
+fn is_label_char(c: char) -> bool {
+    matches!(c, '0'..='9' | 'A'..='Z' | 'a'..='z' | '_' | '\u{80}'..=std::char::MAX)
+}
+
+/// Advances a position past a piece of text.
+fn walk(mut position: Position, text: &str) -> Position {
+    text.chars().for_each(|c| position.advance(c));
+    position
+}
+
+/// Appends a character to a byte string.
+fn push_char(raw: &mut Vec<u8>, c: char) {
+    raw.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+/// Completes a double quoted literal, which escapes may have made invalid
+/// UTF-8.
+fn bytes_token(raw: Vec<u8>) -> Token {
+    match String::from_utf8(raw) {
+        Ok(x) => Token::DoubleQuote(x),
+        Err(err) => Token::Bytes(err.into_bytes()),
+    }
+}
+
+/// Processes the escapes of a double quoted literal or a heredoc body,
+/// which is followed by `end`.
+///
+/// In heredocs, `\"` is kept as is. Octal escapes above `\377` wrap around
+/// and unknown escapes like `\d` are kept as is, like in PHP. On error,
+/// returns the offset of the offending character.
+fn unescape(raw: &str, heredoc: bool, end: char) -> Result<Vec<u8>, (usize, LexErrorKind)> {
     use LexErrorKind as K;
-    use State as S;
-    use Token as T;
-    let s = state;
-    let p = position;
-    let tp = token_position;
-    let b = buffer;
-    let d = dest;
-    match s {
-        S::Initial | S::LineComment | S::MultiLineComment | S::MultiLineCommentPrepareExit => (),
-        S::PrepareComment
-        | S::PrepareAssignment
-        | S::SingleQuote
-        | S::DoubleQuote
-        | S::IntegerDecimal
-        | S::PrepareOpenTag
-        | S::PrepareCloseTag
-        | S::SingleQuoteEscape
-        | S::DoubleQuoteEscape
-        | S::DoubleQuoteEscapeControl
-        | S::DoubleQuoteEscapeHex
-        | S::DoubleQuoteEscapeOctal2
-        | S::DoubleQuoteEscapeHexBound
-        | S::DoubleQuoteEscapeHex2
-        | S::PHPTag0
-        | S::PHPTag1
-        | S::PHPTag2
-        | S::DoubleQuoteEscapeOctal3 => return Err(E::new(p, s, K::EOF)),
-        S::Integer => d.update((tp, T::Int(b))),
-        S::Identifier => d.update((tp, T::Identifier(b))),
-        S::Decimal => d.update((tp, T::Float(b))),
+    let mut out = Vec::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    let unexpected = |x: Option<(usize, char)>| match x {
+        Some((i, c)) => (i, K::Unexpected(c)),
+        None => (raw.len(), K::Unexpected(end)),
+    };
+    while let Some((_, c)) = chars.next() {
+        if c != '\\' {
+            push_char(&mut out, c);
+            continue;
+        }
+        let c = match chars.next() {
+            Some((_, c)) => c,
+            None => {
+                out.push(b'\\');
+                break;
+            }
+        };
+        match c {
+            'e' => out.push(0x1b),
+            'f' => out.push(0x0c),
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'v' => out.push(0x0b),
+            '"' if heredoc => out.extend_from_slice(b"\\\""),
+            '$' | '"' | '\\' => push_char(&mut out, c),
+            'u' if chars.peek().map(|x| x.1) == Some('{') => {
+                chars.next();
+                let mut cp = 0u32;
+                loop {
+                    match chars.next() {
+                        Some((j, '}')) => {
+                            let x = char::try_from(cp).map_err(|_| (j, K::InvalidUnicode(cp)))?;
+                            push_char(&mut out, x);
+                            break;
+                        }
+                        Some((j, x)) => match x.to_digit(16) {
+                            Some(d) if cp <= 0x10_FFFF => cp = (cp << 4) | d,
+                            Some(_) => return Err((j, K::InvalidUnicode(cp))),
+                            None => return Err((j, K::Unexpected(x))),
+                        },
+                        None => return Err(unexpected(None)),
+                    }
+                }
+            }
+            'x' if chars.peek().and_then(|x| x.1.to_digit(16)).is_some() => {
+                let mut byte = 0;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|x| x.1.to_digit(16)) {
+                        Some(d) => {
+                            chars.next();
+                            byte = (byte << 4) | d;
+                        }
+                        None => break,
+                    }
+                }
+                out.push(byte as u8);
+            }
+            '0'..='7' => {
+                let mut byte = c as u32 - '0' as u32;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|x| x.1.to_digit(8)) {
+                        Some(d) => {
+                            chars.next();
+                            byte = (byte << 3) | d;
+                        }
+                        None => break,
+                    }
+                }
+                out.push(byte as u8);
+            }
+            _ => {
+                out.push(b'\\');
+                push_char(&mut out, c);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn is_operator_prefix(op: &str) -> bool {
+    OPERATORS.iter().any(|x| x.starts_with(op))
+}
+
+impl Iterator for Lexer {
+    type Item = Result<(Span, Token), LexError>;
+
+    /// Drains the tokens completed so far, then the error, if any, once.
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(x) = self.queue.pop_front() {
+            return Some(Ok(x));
+        }
+        match self.error {
+            Some(err) if !self.error_returned => {
+                self.error_returned = true;
+                Some(Err(err))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Pull iterator over the tokens of a string.
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    lexer: Lexer,
+    source: Chars<'a>,
+    done: bool,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<(Span, Token), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.lexer.queue.pop_front() {
+                return Some(Ok(x));
+            }
+            if self.done {
+                return self.lexer.error.take().map(Err);
+            }
+            let res = match self.source.next() {
+                Some(c) => self.lexer.step(c),
+                None => {
+                    self.done = true;
+                    self.lexer.finish()
+                }
+            };
+            if let Err(err) = res {
+                self.done = true;
+                self.lexer.error = Some(err);
+            }
+        }
     }
-    Ok(())
 }
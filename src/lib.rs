@@ -16,9 +16,62 @@ pub(crate) mod kvp;
 
 pub(crate) mod deser;
 
+pub(crate) mod fold;
+
+pub(crate) mod env;
+
 pub(crate) mod ser;
 
-pub use deser::{deser_file, deser_str, DeserError, DeserErrorKind};
-pub use kvp::{KeyValuePair, Value};
-pub use lex::{eof, lex, LexError, LexErrorKind, Position, Token};
-pub use ser::{ser_file, ser_str, ser_write};
+pub(crate) mod cst;
+
+pub(crate) mod path;
+
+pub(crate) mod hash;
+
+pub(crate) mod serde_de;
+
+pub(crate) mod serde_ser;
+
+pub(crate) mod diag;
+
+pub(crate) mod constant;
+
+pub(crate) mod assign;
+
+pub(crate) mod database;
+
+pub(crate) mod configure;
+
+pub(crate) mod define;
+
+pub(crate) mod dir;
+
+pub use assign::{deser_vars_file, deser_vars_file_with, deser_vars_str, deser_vars_str_with};
+pub use configure::{
+    deser_configure_file, deser_configure_file_with, deser_configure_str, deser_configure_str_with,
+};
+pub use constant::ConstantTable;
+pub use cst::{Document, Item, Node, SetNode};
+pub use database::{
+    deser_database_file, deser_database_file_with, deser_database_str, deser_database_str_with,
+};
+pub use define::{
+    deser_defines_file, deser_defines_file_with, deser_defines_str, deser_defines_str_with,
+    update_define,
+};
+pub use deser::{
+    deser_file, deser_file_with, deser_str, deser_str_recover, deser_str_recover_with,
+    deser_str_with, DeserError, DeserErrorKind, DeserOptions, Encoding, Root,
+};
+pub use diag::{Diagnostic, DiagnosticStyle};
+pub use dir::{load_dir, load_dir_with, ser_dir, ser_dir_with};
+pub use env::{DotEnv, Env, EnvMode, EnvSource, Filter, ProcessEnv};
+pub use hash::Hash;
+pub use kvp::{Expr, Key, KeyValuePair, Value};
+pub use lex::{LexError, LexErrorKind, Lexer, Position, Span, Token, Tokens};
+pub use ser::{
+    ser_file, ser_file_with, ser_str, ser_str_with, ser_write, ser_write_with, ArrayStyle,
+    SerOptions,
+};
+pub use serde_de::{from_str, from_str_with};
+pub use serde_ser::{to_string, to_string_with, SerError};
@@ -0,0 +1,109 @@
+use crate::kvp::{Key, KeyValuePair, Value};
+
+/// Splits a dot separated path. The empty path has no segments.
+pub(crate) fn segments(path: &str) -> Vec<&str> {
+    if path.is_empty() {
+        Vec::new()
+    } else {
+        path.split('.').collect()
+    }
+}
+
+/// Index of the element with a key. When a key occurs more than once, the
+/// last element wins, like in PHP.
+pub(crate) fn position(items: &[KeyValuePair], key: &Key) -> Option<usize> {
+    items.iter().rposition(|x| x.key == *key)
+}
+
+impl Value {
+    /// Reads a value by a dot separated path, like `Configure::read()`.
+    ///
+    /// Numeric segments address list entries. The empty path is the value
+    /// itself.
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let mut value = self;
+        for segment in segments(path) {
+            value = match value {
+                Value::Set(items) => &items[position(items, &Key::from(segment))?].value,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Mutably reads a value by a dot separated path.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Value> {
+        let mut value = self;
+        for segment in segments(path) {
+            value = match value {
+                Value::Set(items) => {
+                    let i = position(items, &Key::from(segment))?;
+                    &mut items[i].value
+                }
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Writes a value by a dot separated path, like `Configure::write()`.
+    ///
+    /// Missing sets along the path are created, and values in the way of the
+    /// path are replaced by sets.
+    pub fn set_path(&mut self, path: &str, new: Value) {
+        let mut value = self;
+        for segment in segments(path) {
+            if !matches!(value, Value::Set(_)) {
+                *value = Value::Set(Vec::new());
+            }
+            let items = match value {
+                Value::Set(items) => items,
+                _ => unreachable!(),
+            };
+            let key = Key::from(segment);
+            let i = match position(items, &key) {
+                Some(i) => i,
+                None => {
+                    items.push(KeyValuePair::new(key, Value::Null));
+                    items.len() - 1
+                }
+            };
+            value = &mut items[i].value;
+        }
+        *value = new;
+    }
+
+    /// Removes a value by a dot separated path, like `Configure::delete()`.
+    ///
+    /// Returns the removed value, if there was one.
+    pub fn delete_path(&mut self, path: &str) -> Option<Value> {
+        let segments = segments(path);
+        let (last, parents) = segments.split_last()?;
+        let items = match self.get_path_mut(&parents.join("."))? {
+            Value::Set(items) => items,
+            _ => return None,
+        };
+        let key = Key::from(*last);
+        let mut removed = None;
+        for kvp in std::mem::take(items) {
+            if kvp.key == key {
+                removed = Some(kvp.value);
+            } else {
+                items.push(kvp);
+            }
+        }
+        removed
+    }
+
+    /// Checks whether a path holds a value other than null, like
+    /// `Configure::check()`.
+    pub fn has_path(&self, path: &str) -> bool {
+        !matches!(self.get_path(path), None | Some(Value::Null))
+    }
+
+    /// Reads and removes a value by a dot separated path, like
+    /// `Configure::consume()`.
+    pub fn consume_path(&mut self, path: &str) -> Option<Value> {
+        self.delete_path(path)
+    }
+}
@@ -1,84 +1,168 @@
-use std::io::Write;
+use crate::kvp::{KeyValuePair, NextIndex, Value};
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
-use crate::{KeyValuePair, Value};
 
-/// todo
-pub fn ser_str(output: &[KeyValuePair]) -> String {
-    let mut tabs = String::from("\t");
-    let mut buf = String::new();
-    buf.push_str("<?php\nreturn [\n");
-    ser_str_ex(output, &mut tabs, &mut buf);
-    buf.push_str("];\n");
-    buf
+/// Syntax used for writing sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ArrayStyle {
+    /// Short syntax. (`[...]`)
+    #[default]
+    Short,
+
+    /// Long syntax, as used by CakePHP 2.x. (`array(...)`)
+    Long,
 }
 
-fn ser_str_ex(output: &[KeyValuePair], tabs: &mut String, buf: &mut String) {
-    for kvp in output {
-        buf.push_str(&tabs);
-        buf.push_str(&kvp.key_prefix());
+impl ArrayStyle {
+    fn open(self) -> &'static str {
+        match self {
+            Self::Short => "[",
+            Self::Long => "array(",
+        }
+    }
 
-        match &kvp.value {
-            Value::Set(put) => {
-                if put.is_empty() {
-                    buf.push_str("[]");
-                } else {
-                    buf.push('[');
-                    tabs.push('\t');
-                    ser_str_ex(&put, tabs, buf);
-                    tabs.pop();
-                    buf.push(']');
-                }
-            }
-            x => {
-                buf.push_str(&format!("{}", x));
-            }
+    fn close(self) -> &'static str {
+        match self {
+            Self::Short => "]",
+            Self::Long => ")",
         }
-        buf.push_str(",\n")
     }
 }
 
-/// todo
+/// Serialization options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SerOptions {
+    /// Syntax used for writing sets.
+    pub array_style: ArrayStyle,
+
+    /// Writes strings spanning several lines as indented nowdocs, which
+    /// needs PHP 7.3 or later.
+    pub heredoc: bool,
+}
+
+/// Serialize to a string.
+pub fn ser_str(output: &[KeyValuePair]) -> String {
+    ser_str_with(output, &SerOptions::default())
+}
+
+/// Serialize to a string with options.
+pub fn ser_str_with(output: &[KeyValuePair], options: &SerOptions) -> String {
+    let mut buf = Vec::new();
+    ser_write_with(output, &mut buf, options).expect("writing to a Vec cannot fail");
+    String::from_utf8(buf).expect("serialized output is UTF-8")
+}
+
+/// Serialize to a file.
 pub fn ser_file<P: AsRef<Path>>(output: &[KeyValuePair], path: P) -> std::io::Result<()> {
-    let mut buf = File::create(path)?;
-    let mut tabs = String::from("\t");
-    write!(&mut buf, "<?php\nreturn [\n")?;
-    ser_buf_ex(output, &mut tabs, &mut buf)?;
-    write!(&mut buf, "];\n")?;
-    Ok(())
+    ser_file_with(output, path, &SerOptions::default())
+}
+
+/// Serialize to a file with options.
+pub fn ser_file_with<P: AsRef<Path>>(
+    output: &[KeyValuePair],
+    path: P,
+    options: &SerOptions,
+) -> std::io::Result<()> {
+    ser_write_with(output, File::create(path)?, options)
 }
 
-/// todo
-pub fn ser_write<W: Write>(output: &[KeyValuePair], mut buf: W) -> std::io::Result<()> {
+/// Serialize to a writer.
+pub fn ser_write<W: Write>(output: &[KeyValuePair], buf: W) -> std::io::Result<()> {
+    ser_write_with(output, buf, &SerOptions::default())
+}
+
+/// Serialize to a writer with options.
+pub fn ser_write_with<W: Write>(
+    output: &[KeyValuePair],
+    mut buf: W,
+    options: &SerOptions,
+) -> std::io::Result<()> {
     let mut tabs = String::from("\t");
-    write!(&mut buf, "<?php\nreturn [\n")?;
-    ser_buf_ex(output, &mut tabs, &mut buf)?;
-    write!(&mut buf, "];\n")?;
+    writeln!(buf, "<?php\nreturn {}", options.array_style.open())?;
+    ser_buf_ex(output, &mut tabs, "\t", &mut buf, options)?;
+    writeln!(buf, "{};", options.array_style.close())?;
     Ok(())
 }
 
+/// Serializes a single value whose line is indented by `indent`, indenting
+/// nested sets by one more `unit` per level.
+pub(crate) fn ser_value(value: &Value, indent: &str, unit: &str, options: &SerOptions) -> String {
+    let mut buf = Vec::new();
+    let mut tabs = indent.to_string();
+    ser_value_ex(value, &mut tabs, unit, &mut buf, options).expect("writing to a Vec cannot fail");
+    String::from_utf8(buf).expect("serialized output is UTF-8")
+}
 
-fn ser_buf_ex<W: Write>(output: &[KeyValuePair], tabs: &mut String, mut buf: W) -> std::io::Result<()> {
+fn ser_buf_ex<W: Write>(
+    output: &[KeyValuePair],
+    tabs: &mut String,
+    unit: &str,
+    buf: &mut W,
+    options: &SerOptions,
+) -> std::io::Result<()> {
+    let mut next = NextIndex::default();
     for kvp in output {
-        write!(&mut buf, "{}{}", tabs, kvp.key_prefix())?;
+        if next.implicit(&kvp.key) {
+            write!(buf, "{}", tabs)?;
+        } else {
+            write!(buf, "{}{}", tabs, kvp.key_prefix())?;
+        }
+        ser_value_ex(&kvp.value, tabs, unit, buf, options)?;
+        writeln!(buf, ",")?;
+    }
+    Ok(())
+}
 
-        match &kvp.value {
-            Value::Set(put) => {
-                if put.is_empty() {
-                    write!(&mut buf, "[]")?;
+fn ser_value_ex<W: Write>(
+    value: &Value,
+    tabs: &mut String,
+    unit: &str,
+    buf: &mut W,
+    options: &SerOptions,
+) -> std::io::Result<()> {
+    let style = options.array_style;
+    match value {
+        Value::Set(put) => {
+            if put.is_empty() {
+                write!(buf, "{}{}", style.open(), style.close())
+            } else {
+                writeln!(buf, "{}", style.open())?;
+                tabs.push_str(unit);
+                ser_buf_ex(put, tabs, unit, buf, options)?;
+                tabs.truncate(tabs.len() - unit.len());
+                write!(buf, "{}{}", tabs, style.close())
+            }
+        }
+        Value::Str(x) if options.heredoc && is_multiline(x) => {
+            let mut label = String::from("EOT");
+            let mut n = 0;
+            while x
+                .lines()
+                .any(|line| line.trim_start().starts_with(label.as_str()))
+            {
+                n += 1;
+                label = format!("EOT{}", n);
+            }
+            tabs.push_str(unit);
+            writeln!(buf, "<<<'{}'", label)?;
+            for line in x.split('\n') {
+                if line.is_empty() {
+                    writeln!(buf)?;
                 } else {
-                    write!(&mut buf, "[")?;
-                    tabs.push('\t');
-                    ser_buf_ex(&put, tabs, &mut buf)?;
-                    tabs.pop();
-                    write!(&mut buf, "]")?;
+                    writeln!(buf, "{}{}", tabs, line)?;
                 }
             }
-            x => {
-                write!(&mut buf, "{}", x)?;
-            }
+            write!(buf, "{}{}", tabs, label)?;
+            tabs.truncate(tabs.len() - unit.len());
+            Ok(())
         }
-        write!(&mut buf, ",\n")?;
+        x => write!(buf, "{}", x),
     }
-    Ok(())
+}
+
+/// Checks whether a string spans several lines and has no control
+/// characters a nowdoc would hide.
+fn is_multiline(x: &str) -> bool {
+    x.contains('\n') && !x.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
 }
@@ -0,0 +1,711 @@
+use crate::cst::{root, Item, Node, SetNode};
+use crate::deser::{DeserError, DeserErrorKind, DeserOptions};
+use crate::kvp::{Key, KeyValuePair, NextIndex, Value};
+use crate::lex::{Position, Span};
+use crate::path::position;
+use crate::ser::ArrayStyle;
+use crate::serde_ser::{from_raw_entry, raw_entry, RAW};
+use serde::de::value::MapDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+impl de::Error for DeserError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::new(Position::default(), DeserErrorKind::Custom(msg.to_string()))
+    }
+}
+
+/// Deserialize a type from a string.
+///
+/// Keyed sets map onto structs and maps, sets whose keys are `0, 1, ...`
+/// onto sequences, and enums use the externally tagged form.
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, DeserError> {
+    from_str_with(input, &DeserOptions::default())
+}
+
+/// Deserialize a type from a string with options.
+pub fn from_str_with<T: DeserializeOwned>(
+    input: &str,
+    options: &DeserOptions<'_>,
+) -> Result<T, DeserError> {
+    let node = root(input, *options)
+        .map_err(|err| err.with_source(input))?
+        .unwrap_or_else(|| Node {
+            span: Span::default(),
+            value: Value::Set(Vec::new()),
+            set: Some(SetNode {
+                open: Span::default(),
+                close: Span::default(),
+                style: ArrayStyle::default(),
+                items: Vec::new(),
+            }),
+        });
+    T::deserialize(NodeDeserializer(&node)).map_err(|err| err.with_source(input))
+}
+
+/// Gives errors raised by visitors the position of the value being visited.
+fn locate(mut err: DeserError, position: Position) -> DeserError {
+    if err.position == Position::default() {
+        err.position = position;
+    }
+    err
+}
+
+/// Deserializer over a node of the syntax tree.
+struct NodeDeserializer<'a>(&'a Node);
+
+impl NodeDeserializer<'_> {
+    fn set(&self) -> Result<&SetNode, DeserError> {
+        match &self.0.set {
+            Some(set) => Ok(set),
+            None => Err(self.unexpected()),
+        }
+    }
+
+    fn unexpected(&self) -> DeserError {
+        use de::Unexpected as U;
+        let unexpected = match &self.0.value {
+            Value::Null => U::Unit,
+            Value::Bool(x) => U::Bool(*x),
+            Value::Int(x) => U::Signed(*x),
+            Value::Float(x) => U::Float(*x),
+            Value::Str(x) => U::Str(x),
+            Value::Bytes(x) => U::Bytes(x),
+            Value::Set(_) => U::Map,
+            Value::Env(_) => U::Other("env() call"),
+            Value::Expr(_) => U::Other("expression"),
+            Value::Error(_) => U::Other("unparsed text"),
+        };
+        let err: DeserError = de::Error::invalid_type(unexpected, &"a set");
+        locate(err, self.0.span.start)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer<'_> {
+    type Error = DeserError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        let position = self.0.span.start;
+        let result = match &self.0.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(x) => visitor.visit_bool(*x),
+            Value::Int(x) => visitor.visit_i64(*x),
+            Value::Float(x) => visitor.visit_f64(*x),
+            Value::Str(x) => visitor.visit_str(x),
+            Value::Bytes(x) => visitor.visit_bytes(x),
+            Value::Set(_) => {
+                let set = self.set()?;
+                if NextIndex::is_list(set.items.iter().map(|x| &x.key)) {
+                    visitor.visit_seq(Elements::new(set))
+                } else {
+                    visitor.visit_map(Elements::new(set))
+                }
+            }
+            Value::Env(_) | Value::Expr(_) | Value::Error(_) => Err(self.unexpected()),
+        };
+        result.map_err(|err| locate(err, position))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        let position = self.0.span.start;
+        match self.0.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+        .map_err(|err| locate(err, position))
+    }
+
+    /// `env()` calls and expressions are passed verbatim to `Value`.
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        match raw_entry(&self.0.value).filter(|_| name == RAW) {
+            Some(entry) => visitor.visit_map(MapDeserializer::new(std::iter::once(entry))),
+            None => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        let position = self.0.span.start;
+        visitor
+            .visit_seq(Elements::new(self.set()?))
+            .map_err(|err| locate(err, position))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        let position = self.0.span.start;
+        visitor
+            .visit_map(Elements::new(self.set()?))
+            .map_err(|err| locate(err, position))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        let position = self.0.span.start;
+        let result = match (&self.0.value, &self.0.set) {
+            (Value::Str(x), _) => visitor.visit_enum(x.as_str().into_deserializer()),
+            (_, Some(set)) if set.items.len() == 1 => visitor.visit_enum(Variant(&set.items[0])),
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other("value"),
+                &"a string or a set with a single element",
+            )),
+        };
+        result.map_err(|err| locate(err, position))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct identifier
+    }
+}
+
+/// Access to the elements of a set, as a sequence or as a map.
+///
+/// Elements overwritten by a later one with the same key are skipped.
+struct Elements<'a> {
+    items: std::vec::IntoIter<&'a Item>,
+    value: Option<&'a Node>,
+}
+
+impl<'a> Elements<'a> {
+    fn new(set: &'a SetNode) -> Self {
+        let mut seen = HashSet::new();
+        let mut items: Vec<_> = set
+            .items
+            .iter()
+            .rev()
+            .filter(|x| seen.insert(&x.key))
+            .collect();
+        items.reverse();
+        Self {
+            items: items.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_> {
+    type Error = DeserError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeserError> {
+        match self.items.next() {
+            Some(item) => seed.deserialize(NodeDeserializer(&item.value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+impl<'de> MapAccess<'de> for Elements<'_> {
+    type Error = DeserError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeserError> {
+        let item = match self.items.next() {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        self.value = Some(&item.value);
+        let position = item.span().start;
+        seed.deserialize(KeyDeserializer(&item.key))
+            .map(Some)
+            .map_err(|err| locate(err, position))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, DeserError> {
+        match self.value.take() {
+            Some(node) => seed.deserialize(NodeDeserializer(node)),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+/// Deserializer over a set element key.
+struct KeyDeserializer<'a>(&'a Key);
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'_> {
+    type Error = DeserError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        match self.0 {
+            Key::Int(x) => visitor.visit_i64(*x),
+            Key::Str(x) => visitor.visit_str(x),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        match self.0 {
+            Key::Int(x) => visitor.visit_string(x.to_string()),
+            Key::Str(x) => visitor.visit_str(x),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        match self.0 {
+            Key::Int(x) => visitor.visit_enum(x.to_string().into_deserializer()),
+            Key::Str(x) => visitor.visit_enum(x.as_str().into_deserializer()),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct seq tuple tuple_struct map struct
+        ignored_any
+    }
+}
+
+/// Access to an externally tagged enum, a set with a single element.
+struct Variant<'a>(&'a Item);
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = DeserError;
+    type Variant = NodeDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), DeserError> {
+        let variant = seed.deserialize(KeyDeserializer(&self.0.key))?;
+        Ok((variant, NodeDeserializer(&self.0.value)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for NodeDeserializer<'_> {
+    type Error = DeserError;
+
+    fn unit_variant(self) -> Result<(), DeserError> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, DeserError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// `env()` calls and expressions are read from this crate's deserializers
+/// through a newtype struct, as a map holding their source text.
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(RAW, ValueVisitor)
+    }
+}
+
+/// Visitor building a value tree from any self-describing format.
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    /// Integers beyond `i64` become floats, like in PHP.
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        match i64::try_from(v) {
+            Ok(x) => Ok(Value::Int(x)),
+            Err(_) => Ok(Value::Float(v as f64)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    /// Bytes that are valid UTF-8 become strings.
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        self.visit_byte_buf(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        match String::from_utf8(v) {
+            Ok(x) => Ok(Value::Str(x)),
+            Err(err) => Ok(Value::Bytes(err.into_bytes())),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut next = NextIndex::default();
+        let mut items = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            items.push(KeyValuePair::new(next.take(), value));
+        }
+        Ok(Value::Set(items))
+    }
+
+    /// Keys are normalized like PHP does, and repeated keys overwrite the
+    /// earlier element in place. A map holding the source text of an `env()`
+    /// call or expression reads back as one.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut items: Vec<KeyValuePair> = Vec::new();
+        while let Some(key) = map.next_key::<Key>()? {
+            let value = map.next_value()?;
+            match position(&items, &key) {
+                Some(i) => items[i].value = value,
+                None => items.push(KeyValuePair::new(key, value)),
+            }
+        }
+        Ok(from_raw_entry(&items).unwrap_or(Value::Set(items)))
+    }
+}
+
+/// Keys are read from any scalar and normalized like PHP does.
+impl<'de> de::Deserialize<'de> for Key {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = deserializer.deserialize_any(ValueVisitor)?;
+        Key::from_value(&value)
+            .ok_or_else(|| de::Error::invalid_type(de::Unexpected::Map, &"a set key"))
+    }
+}
+
+/// Reads a type from a value tree, such as a subtree found by path.
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = DeserError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        (&self).deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        (&self).deserialize_option(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        (&self).deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        (&self).deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Reads a type from a borrowed value tree.
+impl<'de> de::Deserializer<'de> for &Value {
+    type Error = DeserError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(x) => visitor.visit_bool(*x),
+            Value::Int(x) => visitor.visit_i64(*x),
+            Value::Float(x) => visitor.visit_f64(*x),
+            Value::Str(x) => visitor.visit_str(x),
+            Value::Bytes(x) => visitor.visit_bytes(x),
+            Value::Set(items) if NextIndex::is_list(items.iter().map(|x| &x.key)) => {
+                visitor.visit_seq(Pairs::new(items))
+            }
+            Value::Set(items) => visitor.visit_map(Pairs::new(items)),
+            Value::Env(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Other("env() call"),
+                &visitor,
+            )),
+            Value::Expr(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Other("expression"),
+                &visitor,
+            )),
+            Value::Error(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Other("unparsed text"),
+                &visitor,
+            )),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        match self {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    /// `env()` calls and expressions are passed verbatim to `Value`.
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        match raw_entry(self).filter(|_| name == RAW) {
+            Some(entry) => visitor.visit_map(MapDeserializer::new(std::iter::once(entry))),
+            None => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        match self {
+            Value::Str(x) => visitor.visit_enum(x.as_str().into_deserializer()),
+            Value::Set(items) if items.len() == 1 => visitor.visit_enum(&items[0]),
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other("value"),
+                &"a string or a set with a single element",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+/// Access to the elements of a value set, as a sequence or as a map.
+struct Pairs<'a> {
+    items: std::slice::Iter<'a, KeyValuePair>,
+    value: Option<&'a Value>,
+}
+
+impl<'a> Pairs<'a> {
+    fn new(items: &'a [KeyValuePair]) -> Self {
+        Self {
+            items: items.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Pairs<'_> {
+    type Error = DeserError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DeserError> {
+        match self.items.next() {
+            Some(kvp) => seed.deserialize(&kvp.value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+impl<'de> MapAccess<'de> for Pairs<'_> {
+    type Error = DeserError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DeserError> {
+        let kvp = match self.items.next() {
+            Some(kvp) => kvp,
+            None => return Ok(None),
+        };
+        self.value = Some(&kvp.value);
+        seed.deserialize(KeyDeserializer(&kvp.key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, DeserError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for &'a KeyValuePair {
+    type Error = DeserError;
+    type Variant = &'a Value;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), DeserError> {
+        let variant = seed.deserialize(KeyDeserializer(&self.key))?;
+        Ok((variant, &self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &Value {
+    type Error = DeserError;
+
+    fn unit_variant(self) -> Result<(), DeserError> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, DeserError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeserError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
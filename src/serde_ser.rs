@@ -0,0 +1,483 @@
+use crate::deser::{DeserOptions, Parser};
+use crate::kvp::{Expr, Key, KeyValuePair, NextIndex, Value};
+use crate::lex::Span;
+use crate::ser::{ser_str_with, SerOptions};
+use serde::ser::{self, Serialize};
+use std::convert::TryFrom;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// Serialization error.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SerError {
+    /// The value is not written as a set.
+    InvalidRoot,
+
+    /// Map key that is not a string, integer, boolean, float or null.
+    InvalidKey,
+
+    /// Error reported by a `Serialize` implementation.
+    Custom(String),
+}
+
+impl Display for SerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::InvalidRoot => write!(f, "value is not written as a set"),
+            Self::InvalidKey => write!(f, "map key cannot be a set key"),
+            Self::Custom(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// Serialize a type to a string.
+///
+/// Structs and maps become keyed sets, sequences become sets without keys,
+/// `None` becomes `null` and enums use the externally tagged form.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SerError> {
+    to_string_with(value, &SerOptions::default())
+}
+
+/// Serialize a type to a string with options.
+pub fn to_string_with<T: Serialize + ?Sized>(
+    value: &T,
+    options: &SerOptions,
+) -> Result<String, SerError> {
+    match to_value(value)? {
+        Value::Set(x) => Ok(ser_str_with(&x, options)),
+        _ => Err(SerError::InvalidRoot),
+    }
+}
+
+/// Converts a type into a value tree.
+pub(crate) fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, SerError> {
+    value.serialize(ValueSerializer)
+}
+
+/// Name of the newtype struct that `env()` calls and expressions are written
+/// in, like serde_json's `RawValue`.
+pub(crate) const RAW: &str = "$caked::private::Raw";
+
+/// Key of the single-entry map holding the source text of an `env()` call.
+pub(crate) const RAW_ENV: &str = "$caked::private::Env";
+
+/// Key of the single-entry map holding the source text of an expression.
+pub(crate) const RAW_EXPR: &str = "$caked::private::Expr";
+
+/// The key and source text an `env()` call or expression is passed as.
+pub(crate) fn raw_entry(value: &Value) -> Option<(&'static str, String)> {
+    match value {
+        Value::Env(x) => Some((RAW_ENV, x.to_string())),
+        Value::Expr(x) => Some((RAW_EXPR, x.text.clone())),
+        _ => None,
+    }
+}
+
+/// Reads back a set holding the single entry of [`raw_entry`].
+pub(crate) fn from_raw_entry(items: &[KeyValuePair]) -> Option<Value> {
+    let (key, text) = match items {
+        [KeyValuePair {
+            key: Key::Str(key),
+            value: Value::Str(text),
+        }] => (key.as_str(), text),
+        _ => return None,
+    };
+    match key {
+        RAW_ENV => match Parser::new(text, DeserOptions::default()).value() {
+            Ok((_, x @ Value::Env(_))) => Some(x),
+            _ => None,
+        },
+        RAW_EXPR => Some(Value::Expr(Expr::new(text.clone(), Span::default()))),
+        _ => None,
+    }
+}
+
+/// An `env()` call or expression, written as the single entry of a map.
+struct Raw<'a>(&'a Value);
+
+impl Serialize for Raw<'_> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeMap;
+        let (key, text) = raw_entry(self.0).ok_or_else(|| ser::Error::custom("not raw"))?;
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(key, &text)?;
+        map.end()
+    }
+}
+
+/// Serializer producing a value tree.
+struct ValueSerializer;
+
+/// Wraps a value in a set keyed by an enum variant.
+fn tagged(variant: &str, value: Value) -> Value {
+    Value::Set(vec![KeyValuePair::new(Key::from(variant), value)])
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerError;
+    type SerializeSeq = SetSerializer;
+    type SerializeTuple = SetSerializer;
+    type SerializeTupleStruct = SetSerializer;
+    type SerializeTupleVariant = SetSerializer;
+    type SerializeMap = SetSerializer;
+    type SerializeStruct = SetSerializer;
+    type SerializeStructVariant = SetSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerError> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerError> {
+        Ok(Value::Int(v.into()))
+    }
+
+    /// Integers beyond `i64` become floats, like in PHP.
+    fn serialize_u64(self, v: u64) -> Result<Value, SerError> {
+        match i64::try_from(v) {
+            Ok(x) => Ok(Value::Int(x)),
+            Err(_) => Ok(Value::Float(v as f64)),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerError> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerError> {
+        Ok(match std::str::from_utf8(v) {
+            Ok(x) => Value::Str(x.to_string()),
+            Err(_) => Value::Bytes(v.to_vec()),
+        })
+    }
+
+    fn serialize_none(self) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SerError> {
+        Ok(Value::Str(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, SerError> {
+        match (name, value.serialize(self)?) {
+            (RAW, Value::Set(items)) => from_raw_entry(&items)
+                .ok_or_else(|| SerError::Custom("invalid env() call or expression".into())),
+            (_, value) => Ok(value),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, SerError> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(None, len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(None, Some(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(None, Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(Some(variant), Some(len)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(None, len))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(None, Some(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SetSerializer, SerError> {
+        Ok(SetSerializer::new(Some(variant), Some(len)))
+    }
+}
+
+/// Serializer collecting the elements of a set.
+struct SetSerializer {
+    variant: Option<&'static str>,
+    items: Vec<KeyValuePair>,
+    next: NextIndex,
+    key: Option<Key>,
+}
+
+impl SetSerializer {
+    fn new(variant: Option<&'static str>, len: Option<usize>) -> Self {
+        Self {
+            variant,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            next: NextIndex::default(),
+            key: None,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        let key = self.next.take();
+        self.items.push(KeyValuePair::new(key, to_value(value)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, SerError> {
+        let set = Value::Set(self.items);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, set),
+            None => set,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerError> {
+        let key = Key::from_value(&to_value(key)?).ok_or(SerError::InvalidKey)?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return Err(SerError::Custom("value serialized before key".into())),
+        };
+        self.next.observe(&key);
+        self.items.push(KeyValuePair::new(key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        self.items
+            .push(KeyValuePair::new(Key::from(key), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SetSerializer {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        self.finish()
+    }
+}
+
+/// Sets whose keys are `0, 1, ...` are serialized as sequences, other sets
+/// as maps. Unevaluated `env()` calls and expressions are written verbatim
+/// by this crate, and as a map holding their source text by others. Error
+/// nodes are rejected.
+impl Serialize for Value {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::{SerializeMap, SerializeSeq};
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(x) => serializer.serialize_bool(*x),
+            Value::Int(x) => serializer.serialize_i64(*x),
+            Value::Float(x) => serializer.serialize_f64(*x),
+            Value::Str(x) => serializer.serialize_str(x),
+            Value::Bytes(x) => serializer.serialize_bytes(x),
+            Value::Set(items) if NextIndex::is_list(items.iter().map(|x| &x.key)) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for kvp in items {
+                    seq.serialize_element(&kvp.value)?;
+                }
+                seq.end()
+            }
+            Value::Set(items) => {
+                let mut map = serializer.serialize_map(Some(items.len()))?;
+                for kvp in items {
+                    map.serialize_entry(&kvp.key, &kvp.value)?;
+                }
+                map.end()
+            }
+            Value::Env(_) | Value::Expr(_) => serializer.serialize_newtype_struct(RAW, &Raw(self)),
+            Value::Error(_) => Err(ser::Error::custom("cannot serialize unparsed text")),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Key::Int(x) => serializer.serialize_i64(*x),
+            Key::Str(x) => serializer.serialize_str(x),
+        }
+    }
+}
+
+/// Pairs are serialized as a `(key, value)` tuple.
+impl Serialize for KeyValuePair {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.key, &self.value).serialize(serializer)
+    }
+}
@@ -1,8 +1,1353 @@
-// TODO: tests in proper version 
+// TODO: tests in proper version
 #[cfg(test)]
 mod tests {
+    use caked::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn lexer_chunks() {
+        let input = "<?php return ['a' => [\"b\", true]];";
+        let pulled: Vec<_> = Lexer::tokens(input).collect::<Result<_, _>>().unwrap();
+
+        let mut lexer = Lexer::new();
+        for chunk in ["<?php ret", "urn ['", "a' =", "> [\"b\", tr", "ue]];"].iter() {
+            lexer.feed(chunk).unwrap();
+        }
+        lexer.finish().unwrap();
+        let fed: Vec<_> = lexer.collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(pulled, fed);
+        assert_eq!(fed[0].1, Token::Identifier("return".into()));
+        assert_eq!(&input[fed[2].0.start.index..fed[2].0.end.index], "'a'");
+    }
+
+    #[test]
+    fn lexer_error() {
+        let mut tokens = Lexer::tokens("[ 'a', ` ]");
+        assert_eq!(tokens.next().unwrap().unwrap().1, Token::OpenSet);
+        assert!(tokens.next().unwrap().is_ok());
+        assert!(tokens.next().unwrap().is_ok());
+        let err = tokens.next().unwrap().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::Unexpected('`'));
+        assert_eq!(err.position.index, 7);
+        assert!(tokens.next().is_none());
+
+        let mut lexer = Lexer::new();
+        let err = lexer.feed("[ ` ").unwrap_err();
+        assert_eq!(lexer.next().unwrap().unwrap().1, Token::OpenSet);
+        assert_eq!(lexer.next(), Some(Err(err)));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.feed("'a' ]"), Err(err));
+        assert_eq!(lexer.finish(), Err(err));
+        assert_eq!(lexer.next(), None);
+    }
+
+    fn values(input: &str) -> Vec<Value> {
+        deser_str(input)
+            .unwrap()
+            .into_iter()
+            .map(|kvp| kvp.value)
+            .collect()
+    }
+
+    #[test]
+    fn numeric_literals() {
+        assert_eq!(
+            values("[12, -1, 0775, 0o17, 0x1F, 0b1010, 1_000_000, - -3]"),
+            vec![
+                Value::Int(12),
+                Value::Int(-1),
+                Value::Int(0o775),
+                Value::Int(0o17),
+                Value::Int(0x1f),
+                Value::Int(0b1010),
+                Value::Int(1_000_000),
+                Value::Int(3),
+            ]
+        );
+        assert_eq!(
+            values("[.5, 1., 1e-3, 1_0.2_5E1_0, -2.5, 010.5, 07.5e1, 09.5, 00.5e3, 0_9.5, 08e1]"),
+            vec![
+                Value::Float(0.5),
+                Value::Float(1.0),
+                Value::Float(1e-3),
+                Value::Float(10.25e10),
+                Value::Float(-2.5),
+                Value::Float(10.5),
+                Value::Float(75.0),
+                Value::Float(9.5),
+                Value::Float(500.0),
+                Value::Float(9.5),
+                Value::Float(80.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_overflow() {
+        assert_eq!(
+            values("[9223372036854775807, 9223372036854775808, -9223372036854775808, 0xFFFFFFFFFFFFFFFF]"),
+            vec![
+                Value::Int(i64::MAX),
+                Value::Float(9223372036854775808.0),
+                Value::Float(-9223372036854775808.0),
+                Value::Float(18446744073709551615.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn numeric_invalid() {
+        let err = Lexer::tokens("[0179]").nth(1).unwrap().unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::Unexpected('9'));
+        assert_eq!(err.position.index, 4);
+        let mut lexer = Lexer::new();
+        lexer.feed("[08").unwrap();
+        assert_eq!(
+            lexer.finish().unwrap_err().kind,
+            LexErrorKind::Unexpected('8')
+        );
+        for input in [
+            "[08]", "[09]", "[0_8]", "[1_]", "[0x]", "[1e]", "[12abc]", "[1__0]",
+        ]
+        .iter()
+        {
+            match deser_str(input) {
+                Err(DeserError {
+                    kind: DeserErrorKind::Lex(_),
+                    ..
+                }) => (),
+                x => panic!("{}: {:?}", input, x),
+            }
+        }
+    }
+
+    #[test]
+    fn long_array_syntax() {
+        let long = deser_str(
+            "<?php return array('a' => array(1, [2, array()]), 'b' => ARRAY('c' => null,),);",
+        )
+        .unwrap();
+        let short = deser_str("<?php return ['a' => [1, [2, []]], 'b' => ['c' => null]];").unwrap();
+        assert_eq!(long, short);
+
+        let options = SerOptions {
+            array_style: ArrayStyle::Long,
+            ..Default::default()
+        };
+        let written = ser_str_with(&long, &options);
+        assert!(written.starts_with("<?php\nreturn array(\n\t'a' => array(\n"));
+        assert!(!written.contains('['));
+        assert_eq!(deser_str(&written).unwrap(), long);
+        assert_eq!(deser_str(&ser_str(&long)).unwrap(), long);
+    }
+
+    #[test]
+    fn mismatched_array_syntax() {
+        let err = deser_str("<?php return array(1, 2];").unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::UnexpectedToken(Token::CloseSet));
+        let err = deser_str("<?php return [array(1)) ];").unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::UnexpectedToken(Token::CloseParen));
+    }
+
+    const APP: &str = "<?php
+return [
+    'debug' => filter_var(env('DEBUG', false), FILTER_VALIDATE_BOOLEAN),
+    'url' => env('DATABASE_URL', null),
+    'salt' => env('SECURITY_SALT'),
+];
+";
+
+    #[test]
+    fn env_evaluate() {
+        let mut vars = HashMap::new();
+        vars.insert("DEBUG".to_string(), "true".to_string());
+        let options = DeserOptions {
+            env: EnvMode::Evaluate(&vars),
+            ..Default::default()
+        };
+        assert_eq!(
+            deser_str_with(APP, &options).unwrap(),
+            vec![
+                KeyValuePair::new("debug".into(), Value::Bool(true)),
+                KeyValuePair::new("url".into(), Value::Null),
+                KeyValuePair::new("salt".into(), Value::Null),
+            ]
+        );
+
+        let dotenv = DotEnv::parse(
+            "# comment\nexport DEBUG=off\nDATABASE_URL=\"mysql://db\" # trailing\nSECURITY_SALT='a#b'\n",
+        );
+        let options = DeserOptions {
+            env: EnvMode::Evaluate(&dotenv),
+            ..Default::default()
+        };
+        let values: Vec<_> = deser_str_with(APP, &options)
+            .unwrap()
+            .into_iter()
+            .map(|kvp| kvp.value)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                Value::Bool(false),
+                Value::Str("mysql://db".into()),
+                Value::Str("a#b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_keep() {
+        let kept = deser_str(APP).unwrap();
+        assert_eq!(
+            kept[1].value,
+            Value::Env(Env::new("DATABASE_URL".into(), Some(Value::Null)))
+        );
+        let written = ser_str(&kept);
+        assert!(written
+            .contains("'debug' => filter_var(env('DEBUG', false), FILTER_VALIDATE_BOOLEAN),"));
+        assert!(written.contains("'salt' => env('SECURITY_SALT'),"));
+        assert_eq!(deser_str(&written).unwrap(), kept);
+    }
+
+    #[test]
+    fn verbatim_expressions() {
+        let input = "<?php
+use Cake\\Log\\Engine\\FileLog;
+
+return [
+    'className' => \\Cake\\Log\\Engine\\FileLog::class,
+    'path' => LOGS . 'app.log',
+    'callback' => function ($a) { return [$a, 1]; },
+    'arrow' => fn($x) => $x * 2,
+    'zone' => new \\DateTimeZone('UTC'),
+    'level' => -1,
+];
+";
+        let parsed = deser_str(input).unwrap();
+        let texts: Vec<_> = parsed[..5]
+            .iter()
+            .map(|kvp| match &kvp.value {
+                Value::Expr(x) => {
+                    assert_eq!(&input[x.span.start.index..x.span.end.index], x.text);
+                    x.text.as_str()
+                }
+                x => panic!("{:?}", x),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "\\Cake\\Log\\Engine\\FileLog::class",
+                "LOGS . 'app.log'",
+                "function ($a) { return [$a, 1]; }",
+                "fn($x) => $x * 2",
+                "new \\DateTimeZone('UTC')",
+            ]
+        );
+        assert_eq!(parsed[5].value, Value::Int(-1));
+
+        let written = ser_str(&parsed);
+        assert!(written.contains("\t'callback' => function ($a) { return [$a, 1]; },\n"));
+        assert_eq!(deser_str(&written).unwrap(), parsed);
+    }
+
+    #[test]
+    fn missing_comma() {
+        let err = deser_str("<?php return ['a' => 'b' 'c' => 'd'];").unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::MissingComma);
+        assert_eq!(err.position.index, 25);
+    }
+
+    #[test]
+    fn cst_edits() {
+        let input = "<?php
+// header
+return [
+    'debug' => true, // remove me
+    'App' => [
+        'encoding' => \"UTF-8\",   # charset
+        'list' => [1, 2, 3]
+    ],
+    'Datasources' => array(
+        'default' => array(
+            'host' => 'localhost'
+        ),
+    ),
+];
+";
+        let mut doc = Document::parse(input).unwrap();
+        assert_eq!(doc.to_string(), input);
+        assert_eq!(doc.values(), deser_str(input).unwrap());
+        assert_eq!(doc.get("App.list.1"), Some(&Value::Int(2)));
+
+        doc.set("Datasources.default.host", &Value::Str("db".into()))
+            .unwrap();
+        doc.set("Datasources.default.port", &Value::Int(3306))
+            .unwrap();
+        doc.set("Cache.default", &Value::Set(Vec::new())).unwrap();
+        assert_eq!(doc.remove("debug").unwrap(), Some(Value::Bool(true)));
+        assert_eq!(doc.remove("App.list.0").unwrap(), Some(Value::Int(1)));
+        doc.insert_after(
+            "App.encoding",
+            Some(&"timezone".into()),
+            &Value::Str("UTC".into()),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.source(),
+            "<?php
+// header
+return [
+    'App' => [
+        'encoding' => \"UTF-8\",   # charset
+        'timezone' => 'UTC',
+        'list' => [2, 3]
+    ],
+    'Datasources' => array(
+        'default' => array(
+            'host' => 'db',
+            'port' => 3306
+        ),
+    ),
+    'Cache' => [
+        'default' => [],
+    ],
+];
+"
+        );
+
+        let err = doc.set("App.encoding.x", &Value::Null).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::InvalidPath("App.encoding.x".into())
+        );
+        assert_eq!(doc.remove("Missing").unwrap(), None);
+    }
+
+    #[test]
+    fn value_paths() {
+        let mut config = Value::Set(
+            deser_str("<?php return ['Datasources' => ['default' => ['host' => 'localhost', 'port' => null]], 'list' => ['a', '5' => 'b', 'c']];")
+                .unwrap(),
+        );
+        assert_eq!(
+            config.get_path("Datasources.default.host"),
+            Some(&Value::Str("localhost".into()))
+        );
+        assert_eq!(config.get_path("list.6"), Some(&Value::Str("c".into())));
+        assert_eq!(config.get_path("list.1"), None);
+        assert_eq!(config.get_path("Datasources.default.host.x"), None);
+        assert!(config.has_path("list.0"));
+        assert!(!config.has_path("Datasources.default.port"));
+
+        config.set_path("Datasources.default.host", Value::Str("db".into()));
+        config.set_path("Cache.default.duration", Value::Str("+1 hours".into()));
+        config.set_path("list.0.x", Value::Int(1));
+        if let Some(Value::Int(x)) = config.get_path_mut("list.0.x") {
+            *x += 1;
+        }
+        assert_eq!(config.get_path("list.0.x"), Some(&Value::Int(2)));
+        assert_eq!(
+            config.get_path("Cache"),
+            Some(&Value::Set(vec![KeyValuePair::new(
+                "default".into(),
+                Value::Set(vec![KeyValuePair::new(
+                    "duration".into(),
+                    Value::Str("+1 hours".into())
+                )])
+            )]))
+        );
+
+        assert_eq!(config.consume_path("list.5"), Some(Value::Str("b".into())));
+        assert_eq!(config.consume_path("list.5"), None);
+        assert_eq!(
+            config.delete_path("Datasources.default.host"),
+            Some(Value::Str("db".into()))
+        );
+        assert!(!config.has_path("Datasources.default.host"));
+        assert!(config.has_path("Datasources.default"));
+    }
+
+    #[test]
+    fn hash_paths() {
+        let mut data = Value::Set(
+            deser_str(
+                "<?php return [
+                    'Log' => [
+                        'debug' => ['className' => 'File', 'levels' => ['notice', 'debug']],
+                        'error' => ['className' => 'Syslog', 'levels' => null],
+                    ],
+                    'Items' => [
+                        ['id' => 1, 'name' => 'apple', 'price' => 12.5, 'text' => 'x'],
+                        ['id' => '2', 'name' => 'Banana', 'price' => 8, 'active' => true],
+                        ['id' => 3, 'name' => 'cherry', 'price' => '30'],
+                    ],
+                ];",
+            )
+            .unwrap(),
+        );
+        let strs = |values: Vec<&Value>| -> Vec<String> {
+            values.into_iter().map(|x| x.to_string()).collect()
+        };
+
+        assert_eq!(
+            strs(data.extract("Log.{s}.className")),
+            vec!["'File'", "'Syslog'"]
+        );
+        assert_eq!(strs(data.extract("Items.{n}.id")), vec!["1", "'2'", "3"]);
+        assert_eq!(strs(data.extract("Items.{n}[id=2].name")), vec!["'Banana'"]);
+        assert_eq!(
+            strs(data.extract("Items.{n}[price>10].name")),
+            vec!["'apple'", "'cherry'"]
+        );
+        assert_eq!(
+            strs(data.extract("Items.{n}[name=/^[ab]/i][price<=10].id")),
+            vec!["'2'"]
+        );
+        assert_eq!(strs(data.extract("Items.{n}[text].name")), vec!["'apple'"]);
+        assert_eq!(strs(data.extract("Items.{n}[active=1].id")), vec!["'2'"]);
+        assert_eq!(
+            strs(data.extract("Log.{*}.levels.{n}")),
+            vec!["'notice'", "'debug'"]
+        );
+        assert_eq!(data.extract("Log.debug.className").len(), 1);
+        assert_eq!(data.extract("Log.missing"), Vec::<&Value>::new());
+        assert_eq!(
+            Hash::get(&data, "Items.{n}[id>1].name"),
+            Some(&Value::Str("Banana".into()))
+        );
+
+        Hash::insert(
+            &mut data,
+            "Items.{n}[price>10].expensive",
+            Value::Bool(true),
+        );
+        assert_eq!(
+            strs(data.extract("Items.{n}[expensive].id")),
+            vec!["1", "3"]
+        );
+        Hash::remove(&mut data, "Items.{n}[id=3]");
+        Hash::remove(&mut data, "Log.{s}.levels");
+        assert_eq!(strs(data.extract("Items.{n}.id")), vec!["1", "'2'"]);
+        assert!(data.extract("Log.{s}.levels").is_empty());
+    }
+
+    #[test]
+    fn integer_keys() {
+        let parsed = deser_str(
+            "<?php return [0 => 'a', 5 => 'b', 'c', '8' => 'd', '08' => 'e', true => 'f', 2.7 => 'g', null => 'h', -3 => 'i', 'j'];",
+        )
+        .unwrap();
+        let keys: Vec<_> = parsed.iter().map(|kvp| kvp.key.clone()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                Key::Int(0),
+                Key::Int(5),
+                Key::Int(6),
+                Key::Int(8),
+                Key::Str("08".into()),
+                Key::Int(1),
+                Key::Int(2),
+                Key::Str("".into()),
+                Key::Int(-3),
+                Key::Int(9),
+            ]
+        );
+        assert_eq!(Key::from("-12"), Key::Int(-12));
+        assert_eq!(Key::from("-0"), Key::Str("-0".into()));
+        assert_eq!(
+            Key::from("9223372036854775808"),
+            Key::Str("9223372036854775808".into())
+        );
+
+        let list = deser_str("<?php return [['a', 'b'], 3 => 'c', 'd', 'k' => 'e'];").unwrap();
+        let written = ser_str(&list);
+        assert_eq!(
+            written,
+            "<?php\nreturn [\n\t[\n\t\t'a',\n\t\t'b',\n\t],\n\t3 => 'c',\n\t'd',\n\t'k' => 'e',\n];\n"
+        );
+        assert_eq!(deser_str(&written).unwrap(), list);
+        assert!(matches!(
+            deser_str("<?php return [[1] => 'a'];"),
+            Err(DeserError {
+                kind: DeserErrorKind::InvalidKey,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let input = "<?php return [
+    'password' => 'first',
+    'host' => 'localhost',
+    'password' => 'second',
+    0 => 'a',
+    '0' => 'b',
+];";
+        assert_eq!(
+            deser_str(input).unwrap(),
+            vec![
+                KeyValuePair::new("password".into(), Value::Str("second".into())),
+                KeyValuePair::new("host".into(), Value::Str("localhost".into())),
+                KeyValuePair::new(0.into(), Value::Str("b".into())),
+            ]
+        );
+        assert_eq!(
+            Document::parse(input).unwrap().values(),
+            deser_str(input).unwrap()
+        );
+
+        let options = DeserOptions {
+            strict_keys: true,
+            ..Default::default()
+        };
+        let err = deser_str_with(input, &options).unwrap_err();
+        assert_eq!(err.position.index, 73);
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::DuplicateKey("password".into(), Position::new(19, 1, 4))
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct AppConfig {
+        debug: bool,
+        timeout: Option<u32>,
+        salt: Option<String>,
+        hosts: Vec<String>,
+        ports: BTreeMap<u16, String>,
+        engine: Engine,
+        fallback: Engine,
+        mode: Mode,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Engine {
+        File { path: String },
+        Memcached(String, u16),
+        Null,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Strict(u8),
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let input = "<?php
+return [
+    'debug' => true,
+    'timeout' => null,
+    'salt' => 'abc',
+    'hosts' => ['a', 'b'],
+    'ports' => [80 => 'http', 443 => 'https'],
+    'engine' => ['File' => ['path' => '/tmp']],
+    'fallback' => 'Null',
+    'mode' => ['Strict' => 2],
+];
+";
+        let config: AppConfig = from_str(input).unwrap();
+        assert_eq!(
+            config,
+            AppConfig {
+                debug: true,
+                timeout: None,
+                salt: Some("abc".into()),
+                hosts: vec!["a".into(), "b".into()],
+                ports: vec![(80, "http".into()), (443, "https".into())]
+                    .into_iter()
+                    .collect(),
+                engine: Engine::File {
+                    path: "/tmp".into()
+                },
+                fallback: Engine::Null,
+                mode: Mode::Strict(2),
+            }
+        );
+        let written = to_string(&config).unwrap();
+        assert_eq!(deser_str(&written).unwrap(), deser_str(input).unwrap());
+        assert_eq!(from_str::<AppConfig>(&written).unwrap(), config);
+
+        let memcached = to_string(&vec![Engine::Memcached("localhost".into(), 11211)]).unwrap();
+        assert!(memcached.contains("'Memcached' => [\n\t\t\t'localhost',\n\t\t\t11211,"));
+        assert_eq!(to_string(&5), Err(SerError::InvalidRoot));
+
+        let err = from_str::<AppConfig>(&input.replace("'abc'", "[1]")).unwrap_err();
+        assert_eq!(err.position.index, input.find("'abc'").unwrap());
+        assert!(matches!(err.kind, DeserErrorKind::Custom(_)));
+        let err = from_str::<AppConfig>(&input.replace("    'debug' => true,\n", "")).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::Custom("missing field `debug`".into())
+        );
+        assert_eq!(err.position.index, input.find('[').unwrap());
+    }
+
+    #[test]
+    fn serde_expressions() {
+        let input = "<?php
+return [
+    'host' => env('DB_HOST', 'localhost'),
+    'log' => LOGS . 'app.log',
+];
+";
+        let value: Value = from_str(input).unwrap();
+        assert_eq!(Value::Set(deser_str(input).unwrap()), value);
+        let written = to_string(&value).unwrap();
+        assert!(written.contains("'host' => env('DB_HOST', 'localhost'),"));
+        assert!(written.contains("'log' => LOGS . 'app.log',"));
+        assert_eq!(deser_str(&written).unwrap(), deser_str(input).unwrap());
+
+        let config: BTreeMap<String, Value> = from_str(input).unwrap();
+        assert_eq!(to_string(&config).unwrap(), written);
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(json.contains(r#""host":{"$caked::private::Env":"env('DB_HOST', 'localhost')"}"#));
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+        let copy = Value::deserialize(&value).unwrap();
+        assert_eq!(to_string(&copy).unwrap(), written);
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct DbConfig {
+        host: String,
+        port: u16,
+        flags: Vec<i64>,
+        persistent: Option<bool>,
+    }
+
+    #[test]
+    fn serde_values() {
+        let input = "<?php
+return [
+    'Datasources' => [
+        'default' => [
+            'host' => 'localhost',
+            'port' => 3306,
+            'flags' => [1002 => 'SET NAMES utf8'],
+            'persistent' => null,
+        ],
+    ],
+];
+";
+        let value = Value::Set(deser_str(input).unwrap());
+        let default = value.get_path("Datasources.default").unwrap();
+        assert!(DbConfig::deserialize(default).is_err());
+
+        let mut value = value.clone();
+        value.set_path("Datasources.default.flags", Value::Set(vec![]));
+        value.set_path("Datasources.default.flags.0", Value::Int(1));
+        let default = value.get_path("Datasources.default").unwrap().clone();
+        assert_eq!(
+            DbConfig::deserialize(default).unwrap(),
+            DbConfig {
+                host: "localhost".into(),
+                port: 3306,
+                flags: vec![1],
+                persistent: None,
+            }
+        );
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Datasources":{"default":{"host":"localhost","port":3306,"flags":[1],"persistent":null}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+
+        let value: Value =
+            serde_json::from_str(r#"{"5":"a","b":[true,1.5],"-0":18446744073709551615}"#).unwrap();
+        assert_eq!(
+            value,
+            Value::Set(vec![
+                KeyValuePair::new(Key::Int(5), Value::Str("a".into())),
+                KeyValuePair::new(
+                    "b".into(),
+                    Value::Set(vec![
+                        KeyValuePair::new(Key::Int(0), Value::Bool(true)),
+                        KeyValuePair::new(Key::Int(1), Value::Float(1.5)),
+                    ])
+                ),
+                KeyValuePair::new("-0".into(), Value::Float(18446744073709551615.0)),
+            ])
+        );
+        assert_eq!(
+            serde_json::to_string(&KeyValuePair::new(Key::Int(5), Value::Null)).unwrap(),
+            "[5,null]"
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let input = "<?php\nreturn [\n\t'a' => 1\n\t'b' => 2,\n];\n";
+        let err = deser_str(input).unwrap_err();
+        assert_eq!(err.to_string(), "missing comma at line 4, column 2");
+        assert_eq!(
+            err.diagnostic(DiagnosticStyle::Plain).to_string(),
+            "error: missing comma
+ --> <string>:4:2
+  |
+4 |     'b' => 2,
+  |     ^^^ expected `,` before this
+  |
+  = help: missing `,` between elements
+"
+        );
+        let ansi = err.diagnostic(DiagnosticStyle::Ansi).to_string();
+        assert!(ansi.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(ansi.contains("\x1b[1;31m^^^ expected `,` before this\x1b[0m"));
+
+        let input = "<?php return ['a' => `];";
+        let err = Lexer::tokens(input).find_map(Result::err).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected character '`' at line 1, column 22 in state Initial"
+        );
+        assert_eq!(
+            err.diagnostic(input, DiagnosticStyle::Plain).to_string(),
+            "error: unexpected character '`'
+ --> <string>:1:22
+  |
+1 | <?php return ['a' => `];
+  |                      ^ unexpected character
+"
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        let input = "<?php
+return [
+    'a' => 1
+    'b' => [1, 2 3, )],
+    [1] => 'x',
+    'c' => 4,,
+    'd' => foo(,
+];
+";
+        let (items, errors) = deser_str_recover(input);
+        let kinds: Vec<_> = errors.iter().map(|x| x.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DeserErrorKind::MissingComma,
+                DeserErrorKind::MissingComma,
+                DeserErrorKind::UnexpectedToken(Token::CloseParen),
+                DeserErrorKind::InvalidKey,
+                DeserErrorKind::UnexpectedToken(Token::Separator),
+                DeserErrorKind::MissingComma,
+            ]
+        );
+        assert_eq!(errors[5].position.index, input.rfind(';').unwrap());
+        assert_eq!(errors[0].position.index, input.find("'b'").unwrap());
+        assert_eq!(errors[0].source_text(), Some(input));
+        let value = Value::Set(items);
+        assert_eq!(value.get_path("a"), Some(&Value::Int(1)));
+        assert_eq!(value.get_path("b.2"), Some(&Value::Int(3)));
+        assert!(matches!(value.get_path("b.3"), Some(Value::Error(x)) if x.text == ")"));
+        assert!(matches!(value.get_path("0"), Some(Value::Error(x)) if x.text == "[1] => 'x'"));
+        assert_eq!(value.get_path("c"), Some(&Value::Int(4)));
+        assert!(matches!(value.get_path("d"), Some(Value::Expr(_))));
+
+        let (items, errors) = deser_str_recover("<?php return ['a' => [1, 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, DeserErrorKind::Lex(LexErrorKind::EOF));
+        assert_eq!(
+            Value::Set(items).get_path("a"),
+            Some(&Value::Set(vec![
+                KeyValuePair::new(Key::Int(0), Value::Int(1)),
+                KeyValuePair::new(Key::Int(1), Value::Int(2)),
+            ]))
+        );
+
+        let (items, errors) = deser_str_recover("<?php return ['a' => 1];");
+        assert!(errors.is_empty());
+        assert_eq!(items, deser_str("<?php return ['a' => 1];").unwrap());
+    }
+
+    #[test]
+    fn file_errors() {
+        let dir = std::env::temp_dir().join(format!("caked-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let missing = dir.join("missing.php");
+        let err = deser_file(&missing).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            DeserErrorKind::Io(std::io::ErrorKind::NotFound, _)
+        ));
+        assert_eq!(err.path(), Some(missing.as_path()));
+        let io = std::error::Error::source(&err).unwrap();
+        assert!(io.downcast_ref::<std::io::Error>().is_some());
+
+        let legacy = dir.join("legacy.php");
+        std::fs::write(&legacy, b"<?php\nreturn ['name' => 'Caf\xe9'];\n").unwrap();
+        let err = deser_file(&legacy).unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::InvalidUtf8);
+        assert_eq!(err.position, Position::new(28, 1, 22));
+        let options = DeserOptions {
+            encoding: Encoding::Latin1,
+            ..Default::default()
+        };
+        assert_eq!(
+            deser_file_with(&legacy, &options).unwrap(),
+            vec![KeyValuePair::new("name".into(), Value::Str("Café".into()))]
+        );
+
+        let bom = "\u{FEFF}<?php\nreturn ['a' => 1];\n";
+        assert_eq!(
+            deser_str(bom).unwrap(),
+            vec![KeyValuePair::new("a".into(), Value::Int(1))]
+        );
+        assert_eq!(Document::parse(bom).unwrap().to_string(), bom);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn byte_strings() {
+        let input = r#"<?php
+return [
+    'salt' => "\xFF\x00a\xfe",
+    'octal' => "\377\400\101",
+    'utf8' => "\xC3\xA9\u{e9}",
+    'mixed' => "é\xE9\$",
+    'pattern' => "^\d+$",
+    'unknown' => "\a\cA\x\xg\x{41}\u0041\q",
+];
+"#;
+        let items = deser_str(input).unwrap();
+        let value = Value::Set(items.clone());
+        assert_eq!(
+            value.get_path("salt"),
+            Some(&Value::Bytes(vec![0xff, 0x00, b'a', 0xfe]))
+        );
+        assert_eq!(
+            value.get_path("octal"),
+            Some(&Value::Bytes(vec![0xff, 0x00, b'A']))
+        );
+        assert_eq!(value.get_path("utf8"), Some(&Value::Str("éé".into())));
+        assert_eq!(
+            value.get_path("mixed"),
+            Some(&Value::Bytes(b"\xC3\xA9\xE9$".to_vec()))
+        );
+        assert_eq!(
+            value.get_path("pattern"),
+            Some(&Value::Str(r"^\d+$".into()))
+        );
+        assert_eq!(
+            value.get_path("unknown"),
+            Some(&Value::Str(r"\a\cA\x\xg\x{41}\u0041\q".into()))
+        );
+
+        let written = ser_str(&items);
+        assert!(written.contains(r#"'salt' => "\xFF\000a\xFE","#));
+        assert!(written.contains(r#"'mixed' => "é\xE9\$","#));
+        assert_eq!(deser_str(&written).unwrap(), items);
+
+        let bell = vec![
+            KeyValuePair::new(Key::Int(0), Value::Bytes(vec![7, 0xff])),
+            KeyValuePair::new(Key::Int(1), Value::Str("\u{7}".into())),
+        ];
+        let written = ser_str(&bell);
+        assert!(written.contains("\t\"\\x07\\xFF\","));
+        assert!(written.contains("\t\"\\x07\","));
+
+        let bytes = Value::Bytes((0..=255).collect());
+        let written = ser_str(&[KeyValuePair::new(Key::Int(0), bytes.clone())]);
+        assert_eq!(
+            Value::Set(deser_str(&written).unwrap()).get_path("0"),
+            Some(&bytes)
+        );
+    }
+
+    #[test]
+    fn heredocs() {
+        let input = "<?php
+return [
+    'mail' => <<<EOT
+        Hello \"{$name}\",
+          \x41\u{e9}\t\\\"
+
+        EOT,
+    'sql' => <<<'SQL'
+    SELECT *
+    FROM t WHERE a = '\\n'
+    SQL,
+    'empty' => <<<\"X\"
+    X,
+    'shift' => 1 << 2,
+];
+";
+        let value = Value::Set(deser_str(input).unwrap());
+        assert_eq!(
+            value.get_path("mail"),
+            Some(&Value::Str("Hello \"{$name}\",\n  Aé\t\\\"\n".into()))
+        );
+        assert_eq!(
+            value.get_path("sql"),
+            Some(&Value::Str("SELECT *\nFROM t WHERE a = '\\n'".into()))
+        );
+        assert_eq!(value.get_path("empty"), Some(&Value::Str("".into())));
+        assert_eq!(value.get_path("shift"), Some(&Value::Int(4)));
+
+        let err = deser_str("<?php return [<<<EOT\n  a\n b\n  EOT];").unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::Lex(LexErrorKind::InvalidIndentation)
+        );
+        assert_eq!(err.position, Position::new(25, 2, 0));
+
+        let items = vec![
+            KeyValuePair::new("sql".into(), Value::Str("SELECT *\n\nEOT\n".into())),
+            KeyValuePair::new("short".into(), Value::Str("a\rb".into())),
+        ];
+        let options = SerOptions {
+            heredoc: true,
+            ..Default::default()
+        };
+        let written = ser_str_with(&items, &options);
+        assert_eq!(
+            written,
+            "<?php\nreturn [\n\t'sql' => <<<'EOT1'\n\t\tSELECT *\n\n\t\tEOT\n\n\t\tEOT1,\n\t'short' => \"a\\rb\",\n];\n"
+        );
+        assert_eq!(deser_str(&written).unwrap(), items);
+    }
+
+    #[test]
+    fn constant_expressions() {
+        let input = "<?php
+return [
+    'duration' => 60 * 60 * 24,
+    'half' => 7 / 2,
+    'offset' => -1 + 2,
+    'power' => -2 ** 2,
+    'label' => 'v' . 1 . '.' . (1 + 1),
+    'sum' => 'x' . 0.1 + 0.2,
+    'third' => '' . 1 / 3,
+    'large' => '' . 1e14 . ' ' . -1e13 . ' ' . 0.0001 . ' ' . 1.5e-5,
+    'numeric' => '10' + 5,
+    'mask' => 1 << 3 | 1,
+    'loose' => 'abc' == 0,
+    'fallback' => null ?? 'x',
+    'pick' => 0 ?: 'y',
+    'cast' => (int) '12abc',
+    'path' => CACHE . 'models' . DS,
+    'level' => E_ALL & ~E_DEPRECATED,
+    'zero' => 1 / 0,
+];
+";
+        let value = Value::Set(deser_str(input).unwrap());
+        let expected = vec![
+            ("duration", Value::Int(86400)),
+            ("half", Value::Float(3.5)),
+            ("offset", Value::Int(1)),
+            ("power", Value::Int(-4)),
+            ("label", Value::Str("v1.2".into())),
+            ("sum", Value::Str("x0.3".into())),
+            ("third", Value::Str("0.33333333333333".into())),
+            (
+                "large",
+                Value::Str("1.0E+14 -10000000000000 0.0001 1.5E-5".into()),
+            ),
+            ("numeric", Value::Int(15)),
+            ("mask", Value::Int(9)),
+            ("loose", Value::Bool(false)),
+            ("fallback", Value::Str("x".into())),
+            ("pick", Value::Str("y".into())),
+            ("cast", Value::Int(12)),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(value.get_path(path), Some(&expected), "{}", path);
+        }
+        for path in &["path", "level", "zero"] {
+            assert!(
+                matches!(value.get_path(path), Some(Value::Expr(_))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn constant_table() {
+        let input = "<?php
+return [
+    'path' => CACHE . 'models' . DS,
+    'logs' => LOGS,
+    'level' => E_ALL & ~E_DEPRECATED,
+    'grouped' => (E_ALL) & ~E_DEPRECATED,
+    'max' => PHP_INT_MAX,
+    'wrapped' => (true) ? (PHP_INT_MAX) : (DS),
+    'name' => APP_NAME,
+    'prefix' => \\APP_NAME . '_',
+    'unknown' => UNKNOWN . 'x',
+];
+";
+        let mut constants = ConstantTable::new().with_cake_paths("/srv/app/");
+        constants.insert("APP_NAME", Value::Str("shop".into()));
+        let options = DeserOptions {
+            constants: Some(&constants),
+            ..Default::default()
+        };
+        let value = Value::Set(deser_str_with(input, &options).unwrap());
+        let sep = std::path::MAIN_SEPARATOR;
+        let expected = vec![
+            (
+                "path",
+                Value::Str(format!("/srv/app{0}tmp{0}cache{0}models{0}", sep)),
+            ),
+            ("logs", Value::Str(format!("/srv/app{0}logs{0}", sep))),
+            ("level", Value::Int(24575)),
+            ("grouped", Value::Int(24575)),
+            ("max", Value::Int(i64::MAX)),
+            ("wrapped", Value::Int(i64::MAX)),
+            ("name", Value::Str("shop".into())),
+            ("prefix", Value::Str("shop_".into())),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(value.get_path(path), Some(&expected), "{}", path);
+        }
+        assert!(matches!(value.get_path("unknown"), Some(Value::Expr(_))));
+
+        let value = Value::Set(deser_str(input).unwrap());
+        assert!(matches!(value.get_path("max"), Some(Value::Expr(_))));
+
+        let items = vec![
+            KeyValuePair::new(Key::Int(0), Value::Float(f64::INFINITY)),
+            KeyValuePair::new(Key::Int(1), Value::Float(f64::NEG_INFINITY)),
+            KeyValuePair::new(Key::Int(2), Value::Float(f64::NAN)),
+        ];
+        let written = ser_str(&items);
+        assert!(written.contains("\tINF,\n\t-INF,\n\tNAN,\n"));
+        let read = deser_str_with(&written, &options).unwrap();
+        assert_eq!(read[..2], items[..2]);
+        assert!(matches!(read[2].value, Value::Float(x) if x.is_nan()));
+    }
+
+    #[test]
+    fn variable_root() {
+        let input = "<?php
+App::uses('CakeLog', 'Log');
+$config = array(
+    'debug' => 2,
+    'App' => array('encoding' => 'UTF-8'),
+);
+$config['App']['base'] = false;
+$config['Session']['defaults'] = 'php';
+$config['Routes'][] = 'admin';
+$config['Routes'][] = 'api';
+$cache = 'File';
+";
+        let options = DeserOptions {
+            root: Root::Variable("config"),
+            ..Default::default()
+        };
+        let value = Value::Set(deser_str_with(input, &options).unwrap());
+        assert_eq!(value.get_path("debug"), Some(&Value::Int(2)));
+        assert_eq!(
+            value.get_path("App.encoding"),
+            Some(&Value::Str("UTF-8".into()))
+        );
+        assert_eq!(value.get_path("App.base"), Some(&Value::Bool(false)));
+        assert_eq!(
+            value.get_path("Session.defaults"),
+            Some(&Value::Str("php".into()))
+        );
+        assert_eq!(value.get_path("Routes.1"), Some(&Value::Str("api".into())));
+        assert_eq!(value.get_path("cache"), None);
+
+        let err = deser_str_with("<?php $config = 5;", &options).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::InvalidRootVariable("config".into())
+        );
+        let err = deser_str_with("<?php $conifg = [];", &options).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::InvalidRootVariable("config".into())
+        );
+        let err = deser_str_with("<?php\n", &options).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DeserErrorKind::InvalidRootVariable("config".into())
+        );
+        let diagnostic = err.diagnostic(DiagnosticStyle::Plain).to_string();
+        assert!(diagnostic.contains("expected `$config = [...]`"));
+        assert!(diagnostic.contains("like `$config = [];`"));
+        let err =
+            deser_str_with("<?php $config['a'] = 1; $config['a']['b'] = 2;", &options).unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::InvalidPath("config.a".into()));
+        assert_eq!(err.position.index, 24);
+    }
+
+    #[test]
+    fn database_config() {
+        let input = "<?php
+App::uses('ConnectionManager', 'Model');
+
+class DATABASE_CONFIG {
+    public $default = array(
+        'datasource' => 'Database/Mysql',
+        'persistent' => false,
+        'host' => 'localhost',
+        'login' => 'user',
+        'password' => 'secret',
+        'database' => 'app',
+        'encoding' => 'utf8',
+    );
+
+    var $test = array(
+        'datasource' => 'Mongodb.MongodbSource',
+        'database' => 'app_test',
+    );
+
+    public static $shared = array();
+    private $hidden = array('login' => 'root');
+
+    public function __construct() {
+        $this->default['host'] = env('DB_HOST');
+    }
+}
+";
+        let items = deser_database_str(input).unwrap();
+        let keys: Vec<_> = items.iter().map(|kvp| kvp.key.clone()).collect();
+        assert_eq!(keys, vec![Key::from("default"), Key::from("test")]);
+        let value = Value::Set(items);
+        let expected = vec![
+            ("default.className", "Cake\\Database\\Connection"),
+            ("default.driver", "Cake\\Database\\Driver\\Mysql"),
+            ("default.username", "user"),
+            ("default.database", "app"),
+            ("test.datasource", "Mongodb.MongodbSource"),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(
+                value.get_path(path),
+                Some(&Value::Str(expected.into())),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(value.get_path("default.datasource"), None);
+        assert_eq!(value.get_path("default.login"), None);
+
+        assert_eq!(deser_database_str("<?php return [];").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn configure_statements() {
+        let input = "<?php
+Configure::write('debug', 2);
+Configure::write('Session', array(
+    'defaults' => 'php',
+));
+Configure::write('Session.timeout', 60 * 24);
+Configure::write(array('App.encoding' => 'UTF-8', 'Asset.timestamp' => true));
+App::uses('CakeLog', 'Log');
+Cache::config('default', array('engine' => 'File'));
+if (Configure::read('debug')) {
+    Configure::write('Cache.disable', true);
+}
+CakePlugin::load('DebugKit');
+CakePlugin::load(array('Migrations', 'Search' => array('bootstrap' => true)));
+CakePlugin::loadAll();
+Router::VERSION;
+Configure::write('App.fullBaseUrl', 'https://example.com');
+";
+        let (items, skipped) = deser_configure_str(input).unwrap();
+        let value = Value::Set(items);
+        let expected = vec![
+            ("debug", Value::Int(2)),
+            ("Session.defaults", Value::Str("php".into())),
+            ("Session.timeout", Value::Int(1440)),
+            ("App.encoding", Value::Str("UTF-8".into())),
+            ("Asset.timestamp", Value::Bool(true)),
+            ("Cache.default.engine", Value::Str("File".into())),
+            ("Plugins.DebugKit", Value::Set(vec![])),
+            ("Plugins.Migrations", Value::Set(vec![])),
+            ("Plugins.Search.bootstrap", Value::Bool(true)),
+            ("App.fullBaseUrl", Value::Str("https://example.com".into())),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(value.get_path(path), Some(&expected), "{}", path);
+        }
+        assert_eq!(value.get_path("Cache.disable"), None);
+        let skipped: Vec<_> = skipped
+            .iter()
+            .map(|span| &input[span.start.index..span.end.index])
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                "App::uses('CakeLog', 'Log');",
+                "if (Configure::read('debug')) {\n    Configure::write('Cache.disable', true);\n}",
+                "CakePlugin::loadAll();",
+                "Router::VERSION;",
+            ]
+        );
+    }
+
+    #[test]
+    fn wordpress_defines() {
+        let input = "<?php
+// ** Database settings ** //
+define( 'DB_NAME', 'wordpress' );
+define( 'DB_PASSWORD', 'secret' ); // rotated
+define( 'DB_PASSWORD', 'ignored' );
+define( 'WP_DEBUG', false );
+
+$table_prefix = 'wp_';
+
+if ( ! defined( 'ABSPATH' ) ) {
+\tdefine( 'ABSPATH', __DIR__ . '/' );
+}
+
+require_once ABSPATH . 'wp-settings.php';
+";
+        let value = Value::Set(deser_defines_str(input).unwrap());
+        let expected = vec![
+            ("DB_NAME", Value::Str("wordpress".into())),
+            ("DB_PASSWORD", Value::Str("secret".into())),
+            ("WP_DEBUG", Value::Bool(false)),
+            ("$table_prefix", Value::Str("wp_".into())),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(value.get_path(path), Some(&expected), "{}", path);
+        }
+        assert!(matches!(value.get_path("ABSPATH"), Some(Value::Expr(_))));
+
+        let updated = update_define(input, "DB_PASSWORD", &Value::Str("n3w'".into())).unwrap();
+        assert_eq!(updated, input.replacen("'secret'", "'n3w\\''", 1));
+        let added = update_define(input, "WP_HOME", &Value::Str("https://x.test".into())).unwrap();
+        assert!(added.contains(
+            "\tdefine( 'ABSPATH', __DIR__ . '/' );\n\tdefine( 'WP_HOME', 'https://x.test' );\n}"
+        ));
+        let value = Value::Set(deser_defines_str(&added).unwrap());
+        assert_eq!(
+            value.get_path("WP_HOME"),
+            Some(&Value::Str("https://x.test".into()))
+        );
+
+        let err = deser_defines_str("<?php\n$a = 1;\n$a['b'] = 2;\n").unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::InvalidPath("$a".into()));
+        assert_eq!(err.position.line, 2);
+    }
+
+    #[test]
+    fn assignment_statements() {
+        let input = "<?php
+$databases = [];
+$databases['default']['default'] = [
+    'database' => 'drupal',
+    'driver' => 'mysql',
+];
+$settings['hash_salt'] = 'salt';
+$settings['trusted_host_patterns'][] = '^example\\.com$';
+$settings['trusted_host_patterns'][] = '^www\\.example\\.com$';
+$settings['file_private_path'] = $app_root . '/private';
+$settings['cache_prefix'] = 'site';
+$settings['cache_prefix'] .= '_';
+$settings['cache_prefix'] .= PREFIX;
+$settings['retries'] ??= 3;
+$settings['retries'] += 1;
+if (file_exists($app_root . '/settings.local.php')) {
+  include $app_root . '/settings.local.php';
+}
+";
+        let items = deser_vars_str(input).unwrap();
+        let keys: Vec<_> = items.iter().map(|kvp| kvp.key.clone()).collect();
+        assert_eq!(keys, vec![Key::from("databases"), Key::from("settings")]);
+        let value = Value::Set(items);
+        let expected = vec![
+            (
+                "databases.default.default.driver",
+                Value::Str("mysql".into()),
+            ),
+            ("settings.hash_salt", Value::Str("salt".into())),
+            (
+                "settings.trusted_host_patterns.1",
+                Value::Str("^www\\.example\\.com$".into()),
+            ),
+            ("settings.retries", Value::Int(4)),
+        ];
+        for (path, expected) in expected {
+            assert_eq!(value.get_path(path), Some(&expected), "{}", path);
+        }
+        let texts: Vec<_> = ["settings.file_private_path", "settings.cache_prefix"]
+            .iter()
+            .map(|path| match value.get_path(path) {
+                Some(Value::Expr(x)) => x.text.as_str(),
+                x => panic!("{:?}", x),
+            })
+            .collect();
+        assert_eq!(texts, vec!["$app_root . '/private'", "'site_' . (PREFIX)"]);
+
+        let err = deser_vars_str("<?php $a = 1; $a['b'] = 2;").unwrap_err();
+        assert_eq!(err.kind, DeserErrorKind::InvalidPath("a".into()));
+    }
+
+    #[test]
+    fn config_dir() {
+        let dir = std::env::temp_dir().join(format!("caked-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(
+            dir.join("database.php"),
+            "<?php\nreturn ['default' => 'mysql'];\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("app.php"), "<?php\nreturn ['name' => 'Shop'];\n").unwrap();
+        std::fs::write(dir.join("0.php"), "<?php\nreturn [];\n").unwrap();
+        std::fs::write(dir.join("README.md"), "not config").unwrap();
+        std::fs::write(dir.join("nested/skipped.php"), "<?php return [];").unwrap();
+
+        let items = load_dir(&dir).unwrap();
+        let value = Value::Set(items.clone());
+        let keys: Vec<_> = items.iter().map(|kvp| kvp.key.clone()).collect();
+        assert_eq!(
+            keys,
+            vec![Key::Int(0), Key::from("app"), Key::from("database")]
+        );
+        assert_eq!(
+            value.get_path("database.default"),
+            Some(&Value::Str("mysql".into()))
+        );
+
+        let out = dir.join("out");
+        ser_dir(&items, &out).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.join("app.php")).unwrap(),
+            "<?php\nreturn [\n\t'name' => 'Shop',\n];\n"
+        );
+        assert_eq!(load_dir(&out).unwrap(), items);
+
+        let bad = vec![KeyValuePair::new("debug".into(), Value::Bool(true))];
+        let err = ser_dir(&bad, &out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = load_dir(dir.join("missing")).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            DeserErrorKind::Io(std::io::ErrorKind::NotFound, _)
+        ));
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let name = std::ffi::OsStr::from_bytes(b"\xff.php");
+            std::fs::write(out.join(name), "<?php\nreturn [];\n").unwrap();
+            let err = load_dir(&out).unwrap_err();
+            assert_eq!(err.kind, DeserErrorKind::InvalidKey);
+            assert_eq!(err.path(), Some(out.join(name).as_path()));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }